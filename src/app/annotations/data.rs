@@ -1,42 +1,236 @@
+use super::models::AnnotationExtras;
 use sqlx::SqlitePool;
 
-pub async fn get_etag(pool: &SqlitePool, book_id: &str) -> Option<String> {
+pub async fn get_cached_etag(pool: &SqlitePool, book_id: &str, api_key: &str) -> Option<String> {
     sqlx::query_scalar(
         r"
         SELECT etag
-        FROM etags
-        WHERE book_id = $1
+        FROM annotation_cache
+        WHERE book_id = $1 AND api_key = $2
         ",
     )
     .bind(book_id)
+    .bind(api_key)
     .fetch_optional(pool)
     .await
     .expect("Failed to get etag")
 }
 
-pub async fn update_etag(pool: &SqlitePool, book_id: &str, etag: &str) -> () {
+pub async fn get_cached_annotations(pool: &SqlitePool, book_id: &str, api_key: &str) -> Option<String> {
+    sqlx::query_scalar(
+        r"
+        SELECT annotations
+        FROM annotation_cache
+        WHERE book_id = $1 AND api_key = $2
+        ",
+    )
+    .bind(book_id)
+    .bind(api_key)
+    .fetch_optional(pool)
+    .await
+    .expect("Failed to get cached annotations")
+}
+
+pub async fn add_cached_annotations(
+    pool: &SqlitePool,
+    book_id: &str,
+    api_key: &str,
+    etag: &str,
+    annotations: &str,
+) -> () {
     sqlx::query(
         r"
-        INSERT OR REPLACE INTO etags (book_id, etag)
-        VALUES ($1, $2)
+        INSERT OR REPLACE INTO annotation_cache (book_id, api_key, etag, annotations)
+        VALUES ($1, $2, $3, $4)
         ",
     )
     .bind(book_id)
+    .bind(api_key)
     .bind(etag)
+    .bind(annotations)
+    .execute(pool)
+    .await
+    .expect("Failed to cache annotations");
+}
+
+pub async fn delete_cached_annotations(pool: &SqlitePool, book_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM annotation_cache
+        WHERE book_id = $1
+        ",
+    )
+    .bind(book_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete cached annotations");
+}
+
+pub async fn delete_user_cached_annotations(pool: &SqlitePool, book_id: &str, api_key: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM annotation_cache
+        WHERE book_id = $1 AND api_key = $2
+        ",
+    )
+    .bind(book_id)
+    .bind(api_key)
+    .execute(pool)
+    .await
+    .expect("Failed to delete cached annotations");
+}
+
+pub async fn get_annotation_extras(pool: &SqlitePool, book_id: &str) -> Vec<AnnotationExtras> {
+    sqlx::query_as(
+        r"
+        SELECT annotation_id, created, last_modified, color, style, text, bookmark, start_path, end_path, kobo_id
+        FROM annotation_extras
+        WHERE book_id = $1
+        ",
+    )
+    .bind(book_id)
+    .fetch_all(pool)
+    .await
+    .expect("Failed to get annotation extras")
+}
+
+// The creation time of an annotation is kept once recorded
+pub async fn set_annotation_extras(pool: &SqlitePool, book_id: &str, extras: &AnnotationExtras) -> () {
+    sqlx::query(
+        r"
+        INSERT INTO annotation_extras (
+            book_id, annotation_id, created, last_modified, color, style, bookmark, start_path, end_path, kobo_id
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        ON CONFLICT(book_id, annotation_id) DO UPDATE SET
+            last_modified = excluded.last_modified,
+            color = excluded.color,
+            style = excluded.style,
+            bookmark = excluded.bookmark,
+            start_path = excluded.start_path,
+            end_path = excluded.end_path,
+            kobo_id = COALESCE(excluded.kobo_id, annotation_extras.kobo_id)
+        ",
+    )
+    .bind(book_id)
+    .bind(&extras.annotation_id)
+    .bind(&extras.created)
+    .bind(&extras.last_modified)
+    .bind(&extras.color)
+    .bind(&extras.style)
+    .bind(extras.bookmark)
+    .bind(&extras.start_path)
+    .bind(&extras.end_path)
+    .bind(&extras.kobo_id)
+    .execute(pool)
+    .await
+    .expect("Failed to set annotation extras");
+}
+
+pub async fn set_annotation_text(pool: &SqlitePool, book_id: &str, annotation_id: &str, text: &str) -> () {
+    sqlx::query(
+        r"
+        UPDATE annotation_extras
+        SET text = $3
+        WHERE book_id = $1 AND (annotation_id = $2 OR kobo_id = $2)
+        ",
+    )
+    .bind(book_id)
+    .bind(annotation_id)
+    .bind(text)
+    .execute(pool)
+    .await
+    .expect("Failed to set annotation text");
+}
+
+pub async fn delete_annotation_extras(pool: &SqlitePool, book_id: &str, annotation_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM annotation_extras
+        WHERE book_id = $1 AND annotation_id = $2
+        ",
+    )
+    .bind(book_id)
+    .bind(annotation_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete annotation extras");
+}
+
+pub async fn delete_book_annotation_extras(pool: &SqlitePool, book_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM annotation_extras
+        WHERE book_id = $1
+        ",
+    )
+    .bind(book_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete annotation extras");
+}
+
+// Tombstones are found by the id of either Prosa or the Kobo the annotation was created on
+pub async fn get_tombstone(pool: &SqlitePool, book_id: &str, annotation_id: &str) -> Option<String> {
+    sqlx::query_scalar(
+        r"
+        SELECT MAX(deleted)
+        FROM annotation_tombstones
+        WHERE book_id = $1 AND (annotation_id = $2 OR kobo_id = $2)
+        ",
+    )
+    .bind(book_id)
+    .bind(annotation_id)
+    .fetch_one(pool)
+    .await
+    .expect("Failed to get annotation tombstone")
+}
+
+pub async fn add_tombstone(
+    pool: &SqlitePool,
+    book_id: &str,
+    annotation_id: &str,
+    kobo_id: Option<&str>,
+    deleted: &str,
+) -> () {
+    sqlx::query(
+        r"
+        INSERT OR REPLACE INTO annotation_tombstones (book_id, annotation_id, kobo_id, deleted)
+        VALUES ($1, $2, $3, $4)
+        ",
+    )
+    .bind(book_id)
+    .bind(annotation_id)
+    .bind(kobo_id)
+    .bind(deleted)
+    .execute(pool)
+    .await
+    .expect("Failed to add annotation tombstone");
+}
+
+pub async fn delete_tombstone(pool: &SqlitePool, book_id: &str, annotation_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM annotation_tombstones
+        WHERE book_id = $1 AND (annotation_id = $2 OR kobo_id = $2)
+        ",
+    )
+    .bind(book_id)
+    .bind(annotation_id)
     .execute(pool)
     .await
-    .expect("Failed to replace etag");
+    .expect("Failed to delete annotation tombstone");
 }
 
-pub async fn delete_etag(pool: &SqlitePool, book_id: &str) -> () {
+pub async fn delete_book_tombstones(pool: &SqlitePool, book_id: &str) -> () {
     sqlx::query(
         r"
-        DELETE FROM etags
+        DELETE FROM annotation_tombstones
         WHERE book_id = $1
         ",
     )
     .bind(book_id)
     .execute(pool)
     .await
-    .expect("Failed to delete etag");
+    .expect("Failed to delete annotation tombstones");
 }
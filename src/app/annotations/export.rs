@@ -0,0 +1,94 @@
+use super::models::{BOOKMARK_TYPE, ExportedAnnotation, ExportedBook};
+use chrono::DateTime;
+use std::fmt::Write;
+
+/// Renders the annotations of each book as Markdown, grouped under the file name of their chapter.
+/// Chapters and annotations keep the order Prosa lists the annotations in, which isn't the reading order.
+pub fn to_markdown(books: &[ExportedBook]) -> String {
+    let mut markdown = String::new();
+
+    for book in books {
+        let title = book.title.as_deref().unwrap_or(&book.book_id);
+        writeln!(markdown, "# {title}\n").expect("Failed to write markdown");
+
+        if !book.authors.is_empty() {
+            writeln!(markdown, "*{}*\n", book.authors.join(", ")).expect("Failed to write markdown");
+        }
+
+        let mut chapters: Vec<(Option<&str>, Vec<&ExportedAnnotation>)> = Vec::new();
+        for annotation in &book.annotations {
+            let chapter = annotation.chapter.as_deref();
+            match chapters.iter_mut().find(|(name, _)| *name == chapter) {
+                Some((_, annotations)) => annotations.push(annotation),
+                None => chapters.push((chapter, vec![annotation])),
+            }
+        }
+
+        for (chapter, annotations) in chapters {
+            writeln!(markdown, "## {}\n", chapter.unwrap_or("Unknown chapter"))
+                .expect("Failed to write markdown");
+
+            for annotation in annotations {
+                if let Some(text) = &annotation.text {
+                    for line in text.lines() {
+                        writeln!(markdown, "> {line}").expect("Failed to write markdown");
+                    }
+                    markdown.push('\n');
+                }
+
+                if let Some(note) = &annotation.note {
+                    writeln!(markdown, "{note}\n").expect("Failed to write markdown");
+                }
+
+                if annotation.r#type == BOOKMARK_TYPE {
+                    writeln!(markdown, "*Bookmark*\n").expect("Failed to write markdown");
+                } else if annotation.text.is_none() && annotation.note.is_none() {
+                    writeln!(markdown, "*Highlighted passage*\n").expect("Failed to write markdown");
+                }
+            }
+        }
+    }
+
+    markdown
+}
+
+/// Renders the annotations as CSV, using the column layout of the Readwise import format.
+pub fn to_csv(books: &[ExportedBook]) -> String {
+    let mut csv = String::from("Highlight,Title,Author,Note,Date\r\n");
+
+    for book in books {
+        let title = book.title.as_deref().unwrap_or(&book.book_id);
+        let authors = book.authors.join(", ");
+
+        for annotation in &book.annotations {
+            let date = annotation
+                .created
+                .as_deref()
+                .and_then(|created| DateTime::parse_from_rfc3339(created).ok())
+                .map(|created| created.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default();
+
+            let fields = [
+                annotation.text.as_deref().unwrap_or_default(),
+                title,
+                &authors,
+                annotation.note.as_deref().unwrap_or_default(),
+                &date,
+            ];
+
+            let row: Vec<String> = fields.iter().map(|field| escape_csv(field)).collect();
+            csv.push_str(&row.join(","));
+            csv.push_str("\r\n");
+        }
+    }
+
+    csv
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -1,20 +1,28 @@
 use super::{
-    models::{CheckContentRequest, PatchAnnotationsRequest},
+    models::{
+        AnnotationError, CheckContentRequest, ExportAnnotationsQuery, ExportFormat, GetAnnotationsQuery,
+        PatchAnnotationsRequest,
+    },
     service,
 };
-use crate::app::{AppState, Pool, ProsaClient, authentication::AuthToken, error::KoboError};
+use crate::app::{AppState, authentication::AuthToken, error::KoboError};
 use axum::{
     Extension, Json,
-    extract::{Path, State},
-    http::{HeaderMap, HeaderValue, StatusCode},
+    extract::{Path, Query, State},
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    },
     response::IntoResponse,
 };
 
 pub async fn check_for_changes_handler(
-    State(pool): State<Pool>,
+    State(state): State<AppState>,
+    Extension(token): Extension<AuthToken>,
     Json(request): Json<Vec<CheckContentRequest>>,
 ) -> Result<impl IntoResponse, KoboError> {
-    let changed = service::get_changed_annotations(&pool, request).await;
+    let changed =
+        service::get_changed_annotations(&state.pool, &state.prosa_client, request, &token.api_key).await?;
 
     Ok(Json(changed))
 }
@@ -22,10 +30,22 @@ pub async fn check_for_changes_handler(
 pub async fn get_annotations_handler(
     State(state): State<AppState>,
     Path(book_id): Path<String>,
+    Query(query): Query<GetAnnotationsQuery>,
     Extension(token): Extension<AuthToken>,
 ) -> Result<impl IntoResponse, KoboError> {
-    let annotations = service::get_annotations(&state.prosa_client, &book_id, &token.api_key)?;
-    let etag = service::get_etag(&state.pool, &book_id).await;
+    let max_page_size = state.config.annotations.page_size.max(1);
+    let page_size = query.page_size.unwrap_or(max_page_size).clamp(1, max_page_size);
+
+    let annotations = service::get_annotations(
+        &state.pool,
+        &state.prosa_client,
+        &book_id,
+        query.page_offset_token.as_deref(),
+        page_size,
+        &token.api_key,
+    )
+    .await?;
+    let etag = service::get_etag(&state.pool, &state.prosa_client, &book_id, &token.api_key).await?;
 
     let mut headers = HeaderMap::new();
     headers.insert(
@@ -38,11 +58,64 @@ pub async fn get_annotations_handler(
 }
 
 pub async fn patch_annotations_handler(
-    State(client): State<ProsaClient>,
+    State(state): State<AppState>,
     Path(book_id): Path<String>,
     Extension(token): Extension<AuthToken>,
     Json(request): Json<PatchAnnotationsRequest>,
 ) -> Result<impl IntoResponse, KoboError> {
-    service::patch_annotations(&client, &book_id, request, &token.api_key)?;
+    let updated = request.updated_annotations.unwrap_or_default();
+    let deleted = request.deleted_annotation_ids.unwrap_or_default();
+
+    let result = service::patch_annotations(
+        &state.pool,
+        &state.prosa_client,
+        &book_id,
+        updated,
+        deleted,
+        &token.api_key,
+        &token.device_id,
+    )
+    .await;
+
+    // Even a partially applied patch leaves the cached annotations stale
+    service::delete_cached_annotations(&state.pool, &book_id).await;
+
+    result?;
     Ok(StatusCode::NO_CONTENT)
 }
+
+pub async fn export_annotations_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ExportAnnotationsQuery>,
+) -> Result<impl IntoResponse, KoboError> {
+    let api_key = headers
+        .get("api-key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(AnnotationError::MissingApiKey)?;
+
+    let exported = service::export_annotations(
+        &state.pool,
+        &state.prosa_client,
+        query.book_id.as_deref(),
+        query.format,
+        api_key,
+    )
+    .await?;
+
+    let (content_type, extension) = match query.format {
+        ExportFormat::Markdown => ("text/markdown; charset=utf-8", "md"),
+        ExportFormat::Json => ("application/json", "json"),
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "csv"),
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers.insert(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_str(&format!("attachment; filename=\"annotations.{extension}\""))
+            .expect("Failed to create header"),
+    );
+
+    Ok((headers, exported))
+}
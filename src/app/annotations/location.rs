@@ -0,0 +1,77 @@
+use regex::Regex;
+use std::sync::LazyLock;
+use strum_macros::{EnumMessage, EnumProperty};
+
+#[derive(EnumMessage, EnumProperty, Debug)]
+pub enum LocationError {
+    #[strum(message = "MissingSpan")]
+    #[strum(detailed_message = "The annotation location has no span.")]
+    #[strum(props(StatusCode = "400"))]
+    MissingSpan,
+    #[strum(message = "MissingEnd")]
+    #[strum(detailed_message = "The annotation location has no end, but the annotation is not a bookmark.")]
+    #[strum(props(StatusCode = "400"))]
+    MissingEnd,
+    #[strum(message = "UnsupportedPath")]
+    #[strum(detailed_message = "The annotation location path does not point to an element id.")]
+    #[strum(props(StatusCode = "400"))]
+    UnsupportedPath,
+    #[strum(message = "InvalidRange")]
+    #[strum(detailed_message = "The annotation location ends before the first character of its end element.")]
+    #[strum(props(StatusCode = "400"))]
+    InvalidRange,
+}
+
+// Matches [@id='...'] predicates and id('...') calls
+static XPATH_ID: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?:\[@id\s*=\s*|\bid\()(?:'([^']+)'|"([^"]+)")"#).expect("Failed to compile regex")
+});
+
+// Matches id assertions on CFI steps, like /4[kobo.1.1]
+static CFI_ID: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:^|[/!])\d+\[((?:\^.|[^\]^;])+)").expect("Failed to compile regex"));
+
+/// Extracts the id of the element a Kobo annotation path points to.
+/// Supports KEPUB span paths (`span#kobo\.1\.1`), XPath-like paths selecting
+/// an element by id and EPUB CFIs with id assertions.
+pub fn parse_path(path: &str) -> Result<String, LocationError> {
+    let path = path.trim();
+
+    let id = if let Some(cfi) = path
+        .strip_prefix("epubcfi(")
+        .and_then(|cfi| cfi.strip_suffix(')'))
+    {
+        CFI_ID
+            .captures_iter(cfi)
+            .last()
+            .map(|captures| unescape(&captures[1], '^'))
+    } else if let Some((_, id)) = path.split_once('#').filter(|(element, _)| !element.contains('/')) {
+        Some(unescape(id, '\\'))
+    } else if path.starts_with('/') || path.starts_with("id(") {
+        XPATH_ID
+            .captures_iter(path)
+            .last()
+            .and_then(|captures| captures.get(1).or_else(|| captures.get(2)))
+            .map(|id| id.as_str().to_string())
+    } else {
+        None
+    };
+
+    id.filter(|id| !id.is_empty())
+        .ok_or(LocationError::UnsupportedPath)
+}
+
+fn unescape(value: &str, escape: char) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c == escape {
+            unescaped.extend(chars.next());
+        } else {
+            unescaped.push(c);
+        }
+    }
+
+    unescaped
+}
@@ -1,5 +1,8 @@
 mod data;
+mod export;
 mod handlers;
+mod location;
 mod models;
 pub mod routes;
 pub mod service;
+mod text;
@@ -1,9 +1,27 @@
+use super::location::{self, LocationError};
 use crate::{
     app::state::service::unix_millis_to_string,
     client::{ProsaAnnotation, ProsaAnnotationRequest},
 };
 use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
 use std::time::{SystemTime, UNIX_EPOCH};
+use strum_macros::{EnumMessage, EnumProperty};
+
+// Kobo dog-ear bookmarks, which mark a position instead of a range
+pub const BOOKMARK_TYPE: &str = "dogear";
+
+#[derive(EnumMessage, EnumProperty, Debug)]
+pub enum AnnotationError {
+    #[strum(message = "InvalidPageToken")]
+    #[strum(detailed_message = "The provided page offset token is invalid.")]
+    #[strum(props(StatusCode = "400"))]
+    InvalidPageToken,
+    #[strum(message = "MissingApiKey")]
+    #[strum(detailed_message = "The api key must be provided.")]
+    #[strum(props(StatusCode = "400"))]
+    MissingApiKey,
+}
 
 #[derive(Deserialize, Debug)]
 pub struct CheckContentRequest {
@@ -12,6 +30,64 @@ pub struct CheckContentRequest {
     pub etag: String,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetAnnotationsQuery {
+    pub page_offset_token: Option<String>,
+    pub page_size: Option<usize>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Markdown,
+    Json,
+    Csv,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ExportAnnotationsQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+    pub book_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ExportedBook {
+    pub book_id: String,
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub annotations: Vec<ExportedAnnotation>,
+}
+
+#[derive(Serialize)]
+pub struct ExportedAnnotation {
+    pub id: String,
+    pub r#type: String,
+    pub chapter: Option<String>,
+    pub text: Option<String>,
+    pub note: Option<String>,
+    pub color: Option<String>,
+    pub created: Option<String>,
+    pub modified: String,
+}
+
+impl From<Annotation> for ExportedAnnotation {
+    fn from(annotation: Annotation) -> Self {
+        Self {
+            id: annotation.id,
+            r#type: annotation.r#type,
+            chapter: annotation.location.span.map(|span| span.chapter_filename),
+            text: annotation.highlighted_text,
+            note: annotation.note_text,
+            color: annotation.highlight_color,
+            created: annotation.client_created_utc,
+            modified: annotation.client_last_modified_utc,
+        }
+    }
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GetAnnotationsResponse {
@@ -27,10 +103,10 @@ pub struct PatchAnnotationsRequest {
 }
 
 impl GetAnnotationsResponse {
-    pub fn new(annotations: Vec<Annotation>) -> Self {
+    pub fn new(annotations: Vec<Annotation>, next_page_offset_token: Option<String>) -> Self {
         Self {
             annotations,
-            next_page_offset_token: None,
+            next_page_offset_token,
         }
     }
 }
@@ -38,7 +114,15 @@ impl GetAnnotationsResponse {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Annotation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_created_utc: Option<String>,
     pub client_last_modified_utc: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight_style: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlighted_text: Option<String>,
     pub id: String,
     pub location: AnnotationLocation,
     pub note_text: Option<String>,
@@ -48,52 +132,128 @@ pub struct Annotation {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct AnnotationLocation {
-    pub span: AnnotationSpan,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span: Option<AnnotationSpan>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct AnnotationSpan {
     pub chapter_filename: String,
-    pub end_char: u32,
-    pub end_path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_char: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_path: Option<String>,
     pub start_char: u32,
     pub start_path: String,
 }
 
-impl From<ProsaAnnotation> for Annotation {
-    fn from(annotation: ProsaAnnotation) -> Self {
+// Annotation fields Prosa can't store, kept by the middleware
+#[derive(FromRow, Debug)]
+pub struct AnnotationExtras {
+    pub annotation_id: String,
+    pub created: String,
+    pub last_modified: String,
+    pub color: Option<String>,
+    pub style: Option<String>,
+    // Empty once capturing the highlighted text was attempted and failed
+    pub text: Option<String>,
+    pub bookmark: bool,
+    // The paths as sent by the device, which may be XPaths or CFIs rather than KEPUB spans
+    pub start_path: Option<String>,
+    pub end_path: Option<String>,
+    // The id a Kobo created the annotation with, when Prosa stored it under another one
+    pub kobo_id: Option<String>,
+}
+
+impl AnnotationExtras {
+    // Used for annotations created outside of a Kobo, which are first seen now
+    pub fn new(annotation_id: &str) -> Self {
+        let now: i64 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Failed to get time since epoch")
+            .as_millis()
+            .try_into()
+            .expect("Failed to get current timestamp");
+
+        let now = unix_millis_to_string(now);
+
+        Self {
+            annotation_id: annotation_id.to_string(),
+            created: now.clone(),
+            last_modified: now,
+            color: None,
+            style: None,
+            text: None,
+            bookmark: false,
+            start_path: None,
+            end_path: None,
+            kobo_id: None,
+        }
+    }
+
+    pub fn from_annotation(annotation_id: &str, annotation: &Annotation) -> Self {
+        let last_modified = annotation.client_last_modified_utc.clone();
+        let span = annotation.location.span.as_ref();
+
+        Self {
+            annotation_id: annotation_id.to_string(),
+            created: annotation
+                .client_created_utc
+                .clone()
+                .unwrap_or_else(|| last_modified.clone()),
+            last_modified,
+            color: annotation.highlight_color.clone(),
+            style: annotation.highlight_style.clone(),
+            text: None,
+            bookmark: annotation.r#type == BOOKMARK_TYPE,
+            start_path: span.map(|span| span.start_path.clone()),
+            end_path: span.and_then(|span| span.end_path.clone()),
+            kobo_id: (annotation.id != annotation_id).then(|| annotation.id.clone()),
+        }
+    }
+
+    /// Whether the annotation is the one a device refers to, by the id of either Prosa or the Kobo.
+    pub fn matches(&self, id: &str) -> bool {
+        self.annotation_id == id || self.kobo_id.as_deref() == Some(id)
+    }
+}
+
+impl Annotation {
+    pub fn new(annotation: ProsaAnnotation, extras: AnnotationExtras) -> Self {
+        // Bookmarks are stored in Prosa as a single character, but Kobo expects no end
+        let (end_char, end_path) = if extras.bookmark {
+            (None, None)
+        } else {
+            let end_path = extras.end_path.unwrap_or_else(|| span_path(&annotation.end_tag));
+            (Some(annotation.end_char + 1), Some(end_path))
+        };
+
         let span = AnnotationSpan {
             chapter_filename: annotation.source,
-            end_char: annotation.end_char + 1,
-            end_path: format!("span#{}", annotation.end_tag)
-                .to_string()
-                .replace('.', "\\."),
+            end_char,
+            end_path,
             start_char: annotation.start_char,
-            start_path: format!("span#{}", annotation.start_tag)
-                .to_string()
-                .replace('.', "\\."),
+            start_path: extras
+                .start_path
+                .unwrap_or_else(|| span_path(&annotation.start_tag)),
         };
 
-        let location = AnnotationLocation { span };
+        let location = AnnotationLocation { span: Some(span) };
 
         let r#type = match annotation.note {
+            _ if extras.bookmark => BOOKMARK_TYPE.to_string(),
             Some(_) => "note".to_string(),
             None => "highlight".to_string(),
         };
 
-        let now: i64 = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Failed to get time since epoch")
-            .as_millis()
-            .try_into()
-            .expect("Failed to get current timestamp");
-
-        let now = unix_millis_to_string(now);
-
         Self {
-            client_last_modified_utc: now,
-            id: annotation.annotation_id,
+            client_created_utc: Some(extras.created),
+            client_last_modified_utc: extras.last_modified,
+            highlight_color: extras.color,
+            highlight_style: extras.style,
+            highlighted_text: extras.text.filter(|text| !text.is_empty()),
+            id: extras.kobo_id.unwrap_or(annotation.annotation_id),
             location,
             note_text: annotation.note,
             r#type,
@@ -101,29 +261,37 @@ impl From<ProsaAnnotation> for Annotation {
     }
 }
 
-impl From<Annotation> for ProsaAnnotationRequest {
-    fn from(annotation: Annotation) -> Self {
-        ProsaAnnotationRequest {
-            source: annotation.location.span.chapter_filename,
-            start_tag: annotation
-                .location
-                .span
-                .start_path
-                .strip_prefix("span#")
-                .expect("Failed to parse annotation source")
-                .to_string()
-                .replace("\\.", "."),
-            end_tag: annotation
-                .location
-                .span
-                .end_path
-                .strip_prefix("span#")
-                .expect("Failed to parse annotation source")
-                .to_string()
-                .replace("\\.", "."),
-            start_char: annotation.location.span.start_char,
-            end_char: annotation.location.span.end_char - 1,
+// Annotations made outside of a Kobo only have the id of their spans
+fn span_path(tag: &str) -> String {
+    format!("span#{}", tag.replace('.', "\\."))
+}
+
+impl TryFrom<Annotation> for ProsaAnnotationRequest {
+    type Error = LocationError;
+
+    fn try_from(annotation: Annotation) -> Result<Self, Self::Error> {
+        let span = annotation.location.span.ok_or(LocationError::MissingSpan)?;
+        let start_tag = location::parse_path(&span.start_path)?;
+
+        // Prosa needs a range, so bookmarks cover the character they were placed at
+        let (end_tag, end_char) = if annotation.r#type == BOOKMARK_TYPE {
+            (start_tag.clone(), span.start_char)
+        } else {
+            let end_path = span.end_path.ok_or(LocationError::MissingEnd)?;
+            let end_char = span.end_char.ok_or(LocationError::MissingEnd)?;
+
+            // Kobo end characters are exclusive while Prosa ones are inclusive
+            let end_char = end_char.checked_sub(1).ok_or(LocationError::InvalidRange)?;
+            (location::parse_path(&end_path)?, end_char)
+        };
+
+        Ok(ProsaAnnotationRequest {
+            source: span.chapter_filename,
+            start_tag,
+            end_tag,
+            start_char: span.start_char,
+            end_char,
             note: annotation.note_text,
-        }
+        })
     }
 }
@@ -9,12 +9,15 @@ use axum::{
 #[rustfmt::skip]
 pub fn get_routes(state: AppState) -> Router {
     Router::new()
-        .route("/api/v3/content/checkforchanges", post(handlers::check_for_changes_handler))
+        .route("/api/v3/content/checkforchanges", post(handlers::check_for_changes_handler)
+            .route_layer(from_fn_with_state(state.clone(), extract_token_middleware))
+        )
         .route("/api/v3/content/{book_id}/annotations", get(handlers::get_annotations_handler)
             .route_layer(from_fn_with_state(state.clone(), extract_token_middleware))
         )
         .route("/api/v3/content/{book_id}/annotations", patch(handlers::patch_annotations_handler)
             .route_layer(from_fn_with_state(state.clone(), extract_token_middleware))
         )
+        .route("/annotations/export", get(handlers::export_annotations_handler))
         .with_state(state)
 }
@@ -1,45 +1,73 @@
 use super::{
-    data,
-    models::{Annotation, CheckContentRequest, GetAnnotationsResponse, PatchAnnotationsRequest},
+    data, export,
+    models::{
+        Annotation, AnnotationError, AnnotationExtras, BOOKMARK_TYPE, CheckContentRequest, ExportFormat,
+        ExportedBook, GetAnnotationsResponse,
+    },
+    text::BookContent,
 };
 use crate::{
-    app::{ProsaClient, error::KoboError},
-    client::{ProsaAnnotation, prosa::ClientError},
+    app::{
+        ProsaClient,
+        devices::{self, ActivityEvent},
+        error::KoboError,
+        metadata::ContributorRole,
+        state::service::unix_millis_to_string,
+    },
+    client::{ProsaAnnotation, ProsaAnnotationRequest, prosa::ClientError},
 };
-use base64::{Engine, prelude::BASE64_STANDARD};
-use rand::RngCore;
+use base64::{
+    Engine,
+    prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+};
+use chrono::{DateTime, Utc};
+use log::warn;
+use sha2::{Digest, Sha256};
 use sqlx::SqlitePool;
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use strum::EnumMessage;
+use tokio::task;
 
-pub async fn get_etag(pool: &SqlitePool, book_id: &str) -> String {
-    match data::get_etag(pool, book_id).await {
-        Some(tag) => return tag,
-        None => update_etag(pool, book_id).await,
+// Etags are a hash of the annotations a user has on a book, so a device only
+// sees a book as changed when its annotations actually differ from what it has
+pub async fn get_etag(
+    pool: &SqlitePool,
+    client: &ProsaClient,
+    book_id: &str,
+    api_key: &str,
+) -> Result<String, ClientError> {
+    if let Some(etag) = data::get_cached_etag(pool, book_id, api_key).await {
+        return Ok(etag);
     }
 
-    data::get_etag(pool, book_id)
-        .await
-        .expect("Etag should be present")
+    let (etag, _) = refresh_annotations(pool, client, book_id, api_key).await?;
+    Ok(etag)
 }
 
-pub async fn update_etag(pool: &SqlitePool, book_id: &str) -> () {
-    let mut random = [0u8; 32];
-    rand::rng().fill_bytes(&mut random);
-    let etag = BASE64_STANDARD.encode(random);
-
-    data::update_etag(pool, book_id, &etag).await;
+pub async fn invalidate_annotations(pool: &SqlitePool, book_id: &str, api_key: &str) {
+    data::delete_user_cached_annotations(pool, book_id, api_key).await;
 }
 
-pub async fn delete_etag(pool: &SqlitePool, book_id: &str) {
-    data::delete_etag(pool, book_id).await;
+pub async fn delete_annotation_extras(pool: &SqlitePool, book_id: &str) {
+    data::delete_book_annotation_extras(pool, book_id).await;
 }
 
-pub async fn get_changed_annotations(pool: &SqlitePool, books: Vec<CheckContentRequest>) -> Vec<String> {
+pub async fn get_changed_annotations(
+    pool: &SqlitePool,
+    client: &ProsaClient,
+    books: Vec<CheckContentRequest>,
+    api_key: &str,
+) -> Result<Vec<String>, ClientError> {
     let mut changed: Vec<String> = Vec::new();
 
     for book in books {
-        let Some(etag) = data::get_etag(pool, &book.content_id).await else {
-            data::update_etag(pool, &book.content_id, &book.etag).await;
-            continue;
+        let etag = match get_etag(pool, client, &book.content_id, api_key).await {
+            Ok(etag) => etag,
+            Err(ClientError::NotFound | ClientError::Forbidden) => continue,
+            Err(err) => return Err(err),
         };
 
         if etag != book.etag {
@@ -47,47 +75,392 @@ pub async fn get_changed_annotations(pool: &SqlitePool, books: Vec<CheckContentR
         }
     }
 
-    changed
+    Ok(changed)
 }
 
-pub fn get_annotations(
+pub async fn get_annotations(
+    pool: &SqlitePool,
     client: &ProsaClient,
     book_id: &str,
+    page_offset_token: Option<&str>,
+    page_size: usize,
     api_key: &str,
-) -> Result<GetAnnotationsResponse, ClientError> {
+) -> Result<GetAnnotationsResponse, KoboError> {
+    let offset = match page_offset_token {
+        Some(token) => decode_page_offset_token(token)?,
+        None => 0,
+    };
+
+    let annotations = fetch_annotations(pool, client, book_id, api_key).await?;
+    let total = annotations.len();
+
+    let annotations: Vec<Annotation> = annotations.into_iter().skip(offset).take(page_size).collect();
+
+    let next_offset = offset + annotations.len();
+    let next_page_offset_token = (next_offset < total && !annotations.is_empty())
+        .then(|| BASE64_URL_SAFE_NO_PAD.encode(next_offset.to_string()));
+
+    Ok(GetAnnotationsResponse::new(annotations, next_page_offset_token))
+}
+
+pub async fn delete_cached_annotations(pool: &SqlitePool, book_id: &str) {
+    data::delete_cached_annotations(pool, book_id).await;
+}
+
+// Annotations are cached per book and api key together with their etag,
+// so they are only fetched from Prosa again after a sync reports changes
+async fn fetch_annotations(
+    pool: &SqlitePool,
+    client: &ProsaClient,
+    book_id: &str,
+    api_key: &str,
+) -> Result<Vec<Annotation>, ClientError> {
+    if let Some(cached) = data::get_cached_annotations(pool, book_id, api_key).await {
+        return Ok(serde_json::from_str(&cached).expect("Failed to parse cached annotations"));
+    }
+
+    let (_, annotations) = refresh_annotations(pool, client, book_id, api_key).await?;
+    Ok(annotations)
+}
+
+async fn refresh_annotations(
+    pool: &SqlitePool,
+    client: &ProsaClient,
+    book_id: &str,
+    api_key: &str,
+) -> Result<(String, Vec<Annotation>), ClientError> {
     let annotation_ids = client.list_annotations(book_id, api_key)?;
-    let mut annotations: Vec<ProsaAnnotation> = Vec::new();
+    let annotations: Vec<ProsaAnnotation> = client.get_annotations(book_id, &annotation_ids, api_key)?;
+    let mut extras: HashMap<String, AnnotationExtras> = data::get_annotation_extras(pool, book_id)
+        .await
+        .into_iter()
+        .map(|extras| (extras.annotation_id.clone(), extras))
+        .collect();
 
-    for id in annotation_ids {
-        let annotation = client.get_annotation(book_id, &id, api_key)?;
-        annotations.push(annotation);
+    let mut translated = Vec::with_capacity(annotations.len());
+    for annotation in annotations {
+        // Annotations made outside of a Kobo get their timestamps recorded the first time they're seen
+        let annotation_extras = if let Some(annotation_extras) = extras.remove(&annotation.annotation_id) {
+            annotation_extras
+        } else {
+            let annotation_extras = AnnotationExtras::new(&annotation.annotation_id);
+            data::set_annotation_extras(pool, book_id, &annotation_extras).await;
+            annotation_extras
+        };
+
+        translated.push((annotation, annotation_extras));
     }
 
-    let annotations: Vec<Annotation> = annotations.into_iter().map(Into::into).collect();
+    let annotations: Vec<Annotation> = translated
+        .into_iter()
+        .map(|(annotation, extras)| Annotation::new(annotation, extras))
+        .collect();
+
+    let cached = serde_json::to_string(&annotations).expect("Failed to serialize annotations");
+    let etag = BASE64_STANDARD.encode(Sha256::digest(cached.as_bytes()));
+    data::add_cached_annotations(pool, book_id, api_key, &etag, &cached).await;
 
-    Ok(GetAnnotationsResponse::new(annotations))
+    Ok((etag, annotations))
 }
 
-pub fn patch_annotations(
+// Resolves the highlighted text of annotations against the KEPUB of the book. The book is only
+// downloaded when annotations are patched or exported, never while devices poll for changes, and
+// a failed attempt is recorded as empty text so the same book isn't downloaded over and over.
+async fn capture_highlighted_text(
+    pool: &SqlitePool,
     client: &ProsaClient,
     book_id: &str,
-    request: PatchAnnotationsRequest,
     api_key: &str,
-) -> Result<(), KoboError> {
-    for annotation in request.updated_annotations.unwrap_or_default() {
-        let result = client.add_annotation(book_id, annotation.clone().into(), api_key);
-        let note = &annotation.note_text.unwrap_or_default();
+    annotations: Vec<Annotation>,
+) -> HashMap<String, String> {
+    // Bookmarks don't select any text
+    let ranges: Vec<(String, ProsaAnnotationRequest)> = annotations
+        .into_iter()
+        .filter(|annotation| annotation.r#type != BOOKMARK_TYPE)
+        .filter_map(|annotation| {
+            let id = annotation.id.clone();
+            ProsaAnnotationRequest::try_from(annotation)
+                .ok()
+                .map(|range| (id, range))
+        })
+        .collect();
 
-        if let Err(ClientError::Conflict) = result {
-            client.patch_annotation(book_id, &annotation.id, note, api_key)?;
-        } else {
-            result?;
+    if ranges.is_empty() {
+        return HashMap::new();
+    }
+
+    let task_client = client.clone();
+    let task_book_id = book_id.to_string();
+    let task_api_key = api_key.to_string();
+    let texts = task::spawn_blocking(move || {
+        let mut content = match task_client.download_book(&task_book_id, &task_api_key) {
+            Ok(book) => BookContent::new(book),
+            Err(err) => {
+                warn!("Failed to download book {task_book_id} to capture highlighted text: {err:?}");
+                None
+            }
+        };
+
+        if content.is_none() {
+            warn!("Failed to read book {task_book_id} to capture highlighted text");
         }
+
+        ranges
+            .into_iter()
+            .map(|(id, range)| {
+                let text = content
+                    .as_mut()
+                    .and_then(|content| {
+                        content.highlighted_text(
+                            &range.source,
+                            &range.start_tag,
+                            range.start_char as usize,
+                            &range.end_tag,
+                            range.end_char as usize + 1,
+                        )
+                    })
+                    .unwrap_or_default();
+                (id, text)
+            })
+            .collect::<HashMap<String, String>>()
+    })
+    .await
+    .expect("Failed to join highlighted text task");
+
+    for (annotation_id, text) in &texts {
+        data::set_annotation_text(pool, book_id, annotation_id, text).await;
     }
 
-    for annotation_id in request.deleted_annotation_ids.unwrap_or_default() {
-        client.delete_annotation(book_id, &annotation_id, api_key)?;
+    texts
+}
+
+// Page offset tokens encode the position of the next annotation to be returned
+fn decode_page_offset_token(token: &str) -> Result<usize, AnnotationError> {
+    BASE64_URL_SAFE_NO_PAD
+        .decode(token)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .and_then(|offset| offset.parse().ok())
+        .ok_or(AnnotationError::InvalidPageToken)
+}
+
+pub async fn patch_annotations(
+    pool: &SqlitePool,
+    client: &ProsaClient,
+    book_id: &str,
+    updated: Vec<Annotation>,
+    deleted: Vec<String>,
+    api_key: &str,
+    device_id: &str,
+) -> Result<(), ClientError> {
+    // Annotations created on a Kobo are stored under the id Prosa gave them, but the device keeps
+    // referring to them by its own id
+    let extras = data::get_annotation_extras(pool, book_id).await;
+    let find_extras = |id: &str| extras.iter().find(|extras| extras.matches(id));
+
+    let mut captured = Vec::with_capacity(updated.len());
+    for annotation in updated {
+        // An annotation Prosa can't store is reported without blocking the rest of the patch
+        let prosa_annotation = match ProsaAnnotationRequest::try_from(annotation.clone()) {
+            Ok(prosa_annotation) => prosa_annotation,
+            Err(err) => {
+                let event = ActivityEvent::InvalidAnnotationLocation;
+                devices::service::add_activity(
+                    pool,
+                    device_id,
+                    event,
+                    book_id,
+                    &annotation.id,
+                    err.get_message(),
+                )
+                .await;
+                continue;
+            }
+        };
+
+        let known = find_extras(&annotation.id);
+        if let Some(event) = find_conflict(pool, book_id, &annotation, known).await {
+            devices::service::add_activity(pool, device_id, event, book_id, &annotation.id, None).await;
+            continue;
+        }
+
+        let result = client.add_annotation(book_id, prosa_annotation, api_key);
+
+        let annotation_id = if let Err(ClientError::Conflict) = result {
+            let annotation_id = known.map_or(&annotation.id, |extras| &extras.annotation_id);
+            let note = annotation.note_text.as_deref().unwrap_or_default();
+            client.patch_annotation(book_id, annotation_id, note, api_key)?;
+            annotation_id.clone()
+        } else {
+            result?
+        };
+
+        let extras = AnnotationExtras::from_annotation(&annotation_id, &annotation);
+        data::set_annotation_extras(pool, book_id, &extras).await;
+        data::delete_tombstone(pool, book_id, &annotation.id).await;
+
+        captured.push(Annotation {
+            id: annotation_id,
+            ..annotation
+        });
+    }
+
+    capture_highlighted_text(pool, client, book_id, api_key, captured).await;
+
+    for annotation_id in deleted {
+        let known = find_extras(&annotation_id);
+        let kobo_id = known.and_then(|extras| extras.kobo_id.as_deref());
+        let annotation_id = known.map_or(annotation_id.as_str(), |extras| &extras.annotation_id);
+
+        // Another device may have deleted the annotation already
+        match client.delete_annotation(book_id, annotation_id, api_key) {
+            Ok(()) | Err(ClientError::NotFound) => (),
+            Err(err) => return Err(err),
+        }
+
+        data::delete_annotation_extras(pool, book_id, annotation_id).await;
+        data::add_tombstone(pool, book_id, annotation_id, kobo_id, &current_timestamp()).await;
     }
 
     Ok(())
 }
+
+pub async fn export_annotations(
+    pool: &SqlitePool,
+    client: &ProsaClient,
+    book_id: Option<&str>,
+    format: ExportFormat,
+    api_key: &str,
+) -> Result<String, KoboError> {
+    let mut books: Vec<ExportedBook> = Vec::new();
+
+    // Without a specific book, every book of the caller's library that has annotations is exported
+    let book_ids = match book_id {
+        Some(book_id) => {
+            // Cached annotations are read without Prosa, so the api key is checked against the book first
+            client.fetch_book_file_metadata(book_id, api_key)?;
+            vec![book_id.to_string()]
+        }
+        None => client.list_books(api_key)?,
+    };
+
+    for id in book_ids {
+        let book = match export_book(pool, client, &id, book_id.is_none(), api_key).await {
+            Ok(Some(book)) => book,
+            Ok(None) => continue,
+            Err(ClientError::NotFound | ClientError::Forbidden) if book_id.is_none() => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        books.push(book);
+    }
+
+    let exported = match format {
+        ExportFormat::Markdown => export::to_markdown(&books),
+        ExportFormat::Json => serde_json::to_string(&books).expect("Failed to serialize annotations"),
+        ExportFormat::Csv => export::to_csv(&books),
+    };
+
+    Ok(exported)
+}
+
+async fn export_book(
+    pool: &SqlitePool,
+    client: &ProsaClient,
+    book_id: &str,
+    skip_empty: bool,
+    api_key: &str,
+) -> Result<Option<ExportedBook>, ClientError> {
+    let mut annotations = fetch_annotations(pool, client, book_id, api_key).await?;
+    if skip_empty && annotations.is_empty() {
+        return Ok(None);
+    }
+
+    // Annotations made outside of a Kobo get their text the first time they're exported
+    let uncaptured: Vec<AnnotationExtras> = data::get_annotation_extras(pool, book_id)
+        .await
+        .into_iter()
+        .filter(|extras| extras.text.is_none())
+        .collect();
+
+    let pending: Vec<Annotation> = annotations
+        .iter()
+        .filter(|annotation| uncaptured.iter().any(|extras| extras.matches(&annotation.id)))
+        .cloned()
+        .collect();
+
+    let texts = capture_highlighted_text(pool, client, book_id, api_key, pending).await;
+    if !texts.is_empty() {
+        for annotation in &mut annotations {
+            if let Some(text) = texts.get(&annotation.id) {
+                annotation.highlighted_text = Some(text.clone()).filter(|text| !text.is_empty());
+            }
+        }
+
+        data::delete_user_cached_annotations(pool, book_id, api_key).await;
+    }
+
+    let metadata = client.fetch_metadata(book_id, api_key)?;
+
+    let authors = metadata
+        .contributors
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|contributor| ContributorRole::from_prosa(&contributor.role) == Some(ContributorRole::Author))
+        .map(|contributor| contributor.name)
+        .collect();
+
+    Ok(Some(ExportedBook {
+        book_id: book_id.to_string(),
+        title: metadata.title,
+        authors,
+        annotations: annotations.into_iter().map(Into::into).collect(),
+    }))
+}
+
+pub async fn delete_tombstones(pool: &SqlitePool, book_id: &str) {
+    data::delete_book_tombstones(pool, book_id).await;
+}
+
+// Last writer wins: updates older than the stored annotation, or than its deletion, are dropped.
+// Annotations without a parseable timestamp can't be compared, so they are always applied.
+// Kobo only sends the IDs of deleted annotations, so deletions are timed by the server clock
+// while updates are timed by the device clock. A device whose clock runs behind the server
+// can therefore have an update made shortly after a deletion dropped as older than it.
+async fn find_conflict(
+    pool: &SqlitePool,
+    book_id: &str,
+    annotation: &Annotation,
+    extras: Option<&AnnotationExtras>,
+) -> Option<ActivityEvent> {
+    let modified = parse_timestamp(&annotation.client_last_modified_utc)?;
+
+    let deleted = data::get_tombstone(pool, book_id, &annotation.id).await;
+    if deleted
+        .as_deref()
+        .and_then(parse_timestamp)
+        .is_some_and(|deleted| modified <= deleted)
+    {
+        return Some(ActivityEvent::DeletedAnnotationUpdate);
+    }
+
+    let last_modified = extras.and_then(|extras| parse_timestamp(&extras.last_modified))?;
+    (modified < last_modified).then_some(ActivityEvent::StaleAnnotationUpdate)
+}
+
+fn current_timestamp() -> String {
+    let now: i64 = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Failed to get time since epoch")
+        .as_millis()
+        .try_into()
+        .expect("Failed to get current timestamp");
+
+    unix_millis_to_string(now)
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+}
@@ -0,0 +1,166 @@
+use quick_xml::{
+    Reader, XmlVersion,
+    escape::resolve_html5_entity,
+    events::{BytesStart, Event},
+};
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read},
+};
+use zip::ZipArchive;
+
+// Elements that start a new line of text when a highlight spans more than one of them
+const BLOCK_ELEMENTS: [&str; 12] = [
+    "p",
+    "div",
+    "li",
+    "blockquote",
+    "br",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "tr",
+];
+
+// The text of a KEPUB span, along with the block of the chapter it belongs to
+struct Segment {
+    id: String,
+    text: String,
+    block: usize,
+}
+
+/// The chapters of a KEPUB, parsed into their Kobo spans as they are needed.
+pub struct BookContent {
+    archive: ZipArchive<Cursor<Vec<u8>>>,
+    chapters: HashMap<String, Vec<Segment>>,
+}
+
+impl BookContent {
+    pub fn new(book: Vec<u8>) -> Option<Self> {
+        let archive = ZipArchive::new(Cursor::new(book)).ok()?;
+
+        Some(Self {
+            archive,
+            chapters: HashMap::new(),
+        })
+    }
+
+    /// Returns the text between two Kobo spans of a chapter. The start character is
+    /// inclusive and the end character exclusive, as in Kobo annotation locations.
+    pub fn highlighted_text(
+        &mut self,
+        chapter: &str,
+        start_tag: &str,
+        start_char: usize,
+        end_tag: &str,
+        end_char: usize,
+    ) -> Option<String> {
+        if !self.chapters.contains_key(chapter) {
+            let mut content = String::new();
+            self.archive
+                .by_name(chapter)
+                .ok()?
+                .read_to_string(&mut content)
+                .ok()?;
+            self.chapters
+                .insert(chapter.to_string(), parse_segments(&content));
+        }
+
+        let segments = &self.chapters[chapter];
+        let start = segments.iter().position(|segment| segment.id == start_tag)?;
+        let end = segments.iter().position(|segment| segment.id == end_tag)?;
+
+        if start > end {
+            return None;
+        }
+
+        let mut text = String::new();
+        for (index, segment) in segments[start..=end].iter().enumerate() {
+            let index = start + index;
+            let from = if index == start { start_char } else { 0 };
+            let to = if index == end { end_char } else { usize::MAX };
+
+            if index > start && segment.block != segments[index - 1].block {
+                text.push('\n');
+            }
+
+            text.extend(segment.text.chars().take(to).skip(from));
+        }
+
+        let text = text.trim();
+        (!text.is_empty()).then(|| text.to_string())
+    }
+}
+
+fn parse_segments(content: &str) -> Vec<Segment> {
+    let mut reader = Reader::from_str(content);
+    reader.config_mut().check_end_names = false;
+
+    let mut segments = Vec::new();
+    let mut current: Option<(String, String, usize)> = None;
+    let mut block = 0;
+
+    loop {
+        let event = match reader.read_event() {
+            Ok(Event::Eof) | Err(_) => break,
+            Ok(event) => event,
+        };
+
+        match event {
+            Event::Start(element) => match &mut current {
+                Some((_, _, depth)) => *depth += 1,
+                None => current = kobo_span_id(&element).map(|id| (id, String::new(), 1)),
+            },
+            Event::End(element) => match &mut current {
+                Some((_, _, depth)) if *depth > 1 => *depth -= 1,
+                Some(_) => {
+                    let (id, text, _) = current.take().expect("Span should be present");
+                    segments.push(Segment { id, text, block });
+                }
+                None if BLOCK_ELEMENTS.contains(&element.local_name().as_ref()) => block += 1,
+                None => (),
+            },
+            Event::Empty(element)
+                if current.is_none() && BLOCK_ELEMENTS.contains(&element.local_name().as_ref()) =>
+            {
+                block += 1;
+            }
+            Event::Text(text) => {
+                if let Some((_, span_text, _)) = &mut current {
+                    span_text.push_str(&text.xml10_content());
+                }
+            }
+            Event::CData(text) => {
+                if let Some((_, span_text, _)) = &mut current {
+                    span_text.push_str(&text.xml10_content());
+                }
+            }
+            Event::GeneralRef(reference) => {
+                if let Some((_, span_text, _)) = &mut current {
+                    if let Ok(Some(c)) = reference.resolve_char_ref() {
+                        span_text.push(c);
+                    } else if let Some(entity) = resolve_html5_entity(&reference.xml10_content()) {
+                        span_text.push_str(entity);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    segments
+}
+
+fn kobo_span_id(element: &BytesStart) -> Option<String> {
+    if element.local_name().as_ref() != "span" {
+        return None;
+    }
+
+    let id = element.try_get_attribute("id").ok()??;
+    let id = id.normalized_value(XmlVersion::Implicit1_0).ok()?;
+
+    id.starts_with("kobo.").then(|| id.to_string())
+}
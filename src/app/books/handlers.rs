@@ -1,6 +1,7 @@
 use super::service;
 use crate::app::{
-    AppState, annotations, authentication::AuthToken, books::models::BookTokenError, error::KoboError,
+    AppState, annotations, authentication::AuthToken, books::models::BookTokenError, categories, covers,
+    error::KoboError, shelves,
 };
 use axum::{
     Extension,
@@ -29,7 +30,13 @@ pub async fn delete_book_handler(
     Extension(token): Extension<AuthToken>,
 ) -> Result<impl IntoResponse, KoboError> {
     service::delete_book(&state.pool, &state.prosa_client, &book_id, &token.api_key).await?;
-    annotations::service::delete_etag(&state.pool, &book_id).await;
+    annotations::service::delete_cached_annotations(&state.pool, &book_id).await;
+    annotations::service::delete_annotation_extras(&state.pool, &book_id).await;
+    annotations::service::delete_tombstones(&state.pool, &book_id).await;
+    covers::delete_book_tokens(&state.pool, &book_id).await;
+    categories::delete_book_categories(&state.pool, &book_id).await;
+    shelves::delete_book_shelves(&state.pool, &book_id).await;
+    covers::delete_cached_covers(&state.config.cover_cache.path, &book_id).await;
 
     Ok(StatusCode::NO_CONTENT)
 }
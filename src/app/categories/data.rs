@@ -0,0 +1,125 @@
+use super::models::{Category, CategoryError};
+use sqlx::SqlitePool;
+
+pub async fn add_category(pool: &SqlitePool, category: &Category) -> () {
+    sqlx::query(
+        r"
+        INSERT INTO categories (category_id, name)
+        VALUES ($1, $2)
+        ON CONFLICT(category_id) DO UPDATE SET name = excluded.name
+        ",
+    )
+    .bind(&category.id)
+    .bind(&category.name)
+    .execute(pool)
+    .await
+    .expect("Failed to add category");
+}
+
+pub async fn add_book_category(pool: &SqlitePool, book_id: &str, category_id: &str, device_id: &str) -> () {
+    sqlx::query(
+        r"
+        INSERT OR IGNORE INTO book_categories (book_id, category_id, device_id)
+        VALUES ($1, $2, $3)
+        ",
+    )
+    .bind(book_id)
+    .bind(category_id)
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to add book category");
+}
+
+pub async fn get_category(
+    pool: &SqlitePool,
+    category_id: &str,
+    device_id: &str,
+) -> Result<Category, CategoryError> {
+    let category = sqlx::query_as(
+        r"
+        SELECT DISTINCT c.category_id, c.name
+        FROM categories c
+        JOIN book_categories b ON b.category_id = c.category_id
+        WHERE c.category_id = $1 AND b.device_id = $2
+        ",
+    )
+    .bind(category_id)
+    .bind(device_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(category)
+}
+
+pub async fn get_categories(pool: &SqlitePool, device_id: &str) -> Vec<Category> {
+    sqlx::query_as(
+        r"
+        SELECT DISTINCT c.category_id, c.name
+        FROM categories c
+        JOIN book_categories b ON b.category_id = c.category_id
+        WHERE b.device_id = $1
+        ORDER BY c.name
+        ",
+    )
+    .bind(device_id)
+    .fetch_all(pool)
+    .await
+    .expect("Failed to get categories")
+}
+
+pub async fn get_category_books(pool: &SqlitePool, category_id: &str, device_id: &str) -> Vec<String> {
+    sqlx::query_scalar(
+        r"
+        SELECT book_id
+        FROM book_categories
+        WHERE category_id = $1 AND device_id = $2
+        ORDER BY book_id
+        ",
+    )
+    .bind(category_id)
+    .bind(device_id)
+    .fetch_all(pool)
+    .await
+    .expect("Failed to get category books")
+}
+
+pub async fn delete_book_categories(pool: &SqlitePool, book_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM book_categories
+        WHERE book_id = $1
+        ",
+    )
+    .bind(book_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete book categories");
+}
+
+pub async fn delete_device_book_categories(pool: &SqlitePool, book_id: &str, device_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM book_categories
+        WHERE book_id = $1 AND device_id = $2
+        ",
+    )
+    .bind(book_id)
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete book categories");
+}
+
+pub async fn delete_device_categories(pool: &SqlitePool, device_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM book_categories
+        WHERE device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete device categories");
+}
@@ -0,0 +1,63 @@
+use super::service;
+use crate::app::{AppState, authentication::AuthToken, error::KoboError};
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    response::IntoResponse,
+};
+use axum_extra::extract::Host;
+use serde_json::Value;
+
+pub async fn list_categories_handler(
+    State(state): State<AppState>,
+    Extension(token): Extension<AuthToken>,
+) -> impl IntoResponse {
+    Json(service::list_categories(&state.pool, &token.device_id).await)
+}
+
+pub async fn get_category_handler(
+    State(state): State<AppState>,
+    Path(category_id): Path<String>,
+    Extension(token): Extension<AuthToken>,
+) -> Result<impl IntoResponse, KoboError> {
+    let category = service::get_category(&state.pool, &category_id, &token.device_id).await?;
+
+    Ok(Json(category))
+}
+
+pub async fn category_featured_lists_handler(
+    State(state): State<AppState>,
+    Path(category_id): Path<String>,
+    Extension(token): Extension<AuthToken>,
+) -> Result<impl IntoResponse, KoboError> {
+    service::get_category(&state.pool, &category_id, &token.device_id).await?;
+
+    // Prosa has no curated lists, so categories never have featured lists
+    Ok(Json(Vec::<Value>::new()))
+}
+
+pub async fn category_products_handler(
+    State(state): State<AppState>,
+    Host(host): Host,
+    Path(category_id): Path<String>,
+    Extension(token): Extension<AuthToken>,
+) -> Result<impl IntoResponse, KoboError> {
+    let server_url = match &state.config.server.public {
+        Some(s) => format!("{}://{}:{}", s.scheme, s.host, s.port),
+        None if host.contains(':') => format!("http://{host}"),
+        _ => format!("http://{host}:{}", state.config.server.bind.port),
+    };
+
+    let products = service::translate_category_products(
+        &state.pool,
+        &state.prosa_client,
+        &category_id,
+        &server_url,
+        &state.config,
+        &token.api_key,
+        &token.device_id,
+    )
+    .await?;
+
+    Ok(Json(products))
+}
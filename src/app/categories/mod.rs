@@ -0,0 +1,10 @@
+mod data;
+mod handlers;
+mod models;
+pub mod routes;
+mod service;
+
+pub use service::delete_book_categories;
+pub use service::delete_device_book_categories;
+pub use service::delete_device_categories;
+pub use service::translate_genres;
@@ -0,0 +1,33 @@
+use serde::Serialize;
+use sqlx::FromRow;
+use strum_macros::{EnumMessage, EnumProperty};
+
+type SqlxError = sqlx::Error;
+
+#[derive(EnumMessage, EnumProperty, Debug)]
+pub enum CategoryError {
+    #[strum(message = "NotFound")]
+    #[strum(detailed_message = "The requested category was not found.")]
+    #[strum(props(StatusCode = "404"))]
+    NotFound,
+    #[strum(message = "Internal error")]
+    #[strum(props(StatusCode = "500"))]
+    InternalError,
+}
+
+impl From<SqlxError> for CategoryError {
+    fn from(error: SqlxError) -> Self {
+        match error {
+            SqlxError::RowNotFound => CategoryError::NotFound,
+            _ => CategoryError::InternalError,
+        }
+    }
+}
+
+#[derive(Serialize, FromRow, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Category {
+    #[sqlx(rename = "category_id")]
+    pub id: String,
+    pub name: String,
+}
@@ -0,0 +1,14 @@
+use super::handlers;
+use crate::app::{AppState, authentication::middleware::extract_token_middleware};
+use axum::{Router, middleware::from_fn_with_state, routing::get};
+
+#[rustfmt::skip]
+pub fn get_routes(state: AppState) -> Router {
+    Router::new()
+        .route("/v1/categories", get(handlers::list_categories_handler))
+        .route("/v1/categories/{category_id}", get(handlers::get_category_handler))
+        .route("/v1/categories/{category_id}/featured", get(handlers::category_featured_lists_handler))
+        .route("/v1/categories/{category_id}/products", get(handlers::category_products_handler))
+        .layer(from_fn_with_state(state.clone(), extract_token_middleware))
+        .with_state(state)
+}
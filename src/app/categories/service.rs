@@ -0,0 +1,143 @@
+use super::{
+    data,
+    models::{Category, CategoryError},
+};
+use crate::{
+    app::{
+        error::KoboError,
+        metadata::{self, BookMetadata},
+    },
+    client::prosa::{Client, ClientError},
+    config::{Categories, Configuration},
+};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+
+pub async fn translate_genres(
+    pool: &SqlitePool,
+    config: &Categories,
+    book_id: &str,
+    genres: &[String],
+    device_id: &str,
+) -> Vec<Category> {
+    let mut categories: Vec<Category> = Vec::new();
+
+    for genre in genres {
+        let Some(category) = map_genre(config, genre) else {
+            continue;
+        };
+
+        if categories.iter().all(|c| c.id != category.id) {
+            categories.push(category);
+        }
+    }
+
+    data::delete_device_book_categories(pool, book_id, device_id).await;
+
+    for category in &categories {
+        data::add_category(pool, category).await;
+        data::add_book_category(pool, book_id, &category.id, device_id).await;
+    }
+
+    categories
+}
+
+pub async fn list_categories(pool: &SqlitePool, device_id: &str) -> Vec<Category> {
+    data::get_categories(pool, device_id).await
+}
+
+pub async fn get_category(
+    pool: &SqlitePool,
+    category_id: &str,
+    device_id: &str,
+) -> Result<Category, CategoryError> {
+    data::get_category(pool, category_id, device_id).await
+}
+
+pub async fn translate_category_products(
+    pool: &SqlitePool,
+    client: &Client,
+    category_id: &str,
+    server_url: &str,
+    config: &Configuration,
+    api_key: &str,
+    device_id: &str,
+) -> Result<Vec<BookMetadata>, KoboError> {
+    data::get_category(pool, category_id, device_id).await?;
+
+    let mut products = Vec::new();
+    for book_id in data::get_category_books(pool, category_id, device_id).await {
+        // Books that were deleted or are no longer shared with the user are dropped from the category
+        match client.fetch_book_file_metadata(&book_id, api_key) {
+            Ok(_) => (),
+            Err(ClientError::NotFound | ClientError::Forbidden) => {
+                data::delete_device_book_categories(pool, &book_id, device_id).await;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        let product = metadata::service::translate_metadata(
+            pool, client, &book_id, server_url, config, api_key, device_id,
+        )
+        .await?;
+        products.push(product);
+    }
+
+    Ok(products)
+}
+
+pub async fn delete_book_categories(pool: &SqlitePool, book_id: &str) {
+    data::delete_book_categories(pool, book_id).await;
+}
+
+pub async fn delete_device_book_categories(pool: &SqlitePool, book_id: &str, device_id: &str) {
+    data::delete_device_book_categories(pool, book_id, device_id).await;
+}
+
+pub async fn delete_device_categories(pool: &SqlitePool, device_id: &str) {
+    data::delete_device_categories(pool, device_id).await;
+}
+
+fn map_genre(config: &Categories, genre: &str) -> Option<Category> {
+    let genre = genre.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if genre.is_empty() {
+        return None;
+    }
+
+    let key = genre.to_lowercase();
+    let mapping = config
+        .mappings
+        .iter()
+        .find(|m| m.genres.iter().any(|g| g.trim().to_lowercase() == key));
+
+    let category = match mapping {
+        Some(mapping) => Category {
+            id: mapping.id.clone(),
+            name: mapping.name.clone(),
+        },
+        None => Category {
+            id: synthetic_category_id(&key),
+            name: genre,
+        },
+    };
+
+    Some(category)
+}
+
+// Genres without a configured mapping get a category whose ID is derived from the genre itself,
+// so the same genre always lands in the same category across books and devices
+fn synthetic_category_id(genre: &str) -> String {
+    let hash = Sha256::digest(genre.as_bytes());
+    let bytes: [u8; 16] = hash[..16].try_into().expect("Failed to truncate genre hash");
+    let hex = format!("{:032x}", u128::from_be_bytes(bytes));
+
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
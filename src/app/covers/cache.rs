@@ -0,0 +1,97 @@
+use super::models::{CoverOptions, MAX_CACHED_COVERS};
+use log::warn;
+use rand::RngCore;
+use std::{
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
+use tokio::fs;
+
+const ORIGINAL_ENTRY: &str = "original.jpg";
+
+pub async fn get_cover(cache_path: &str, book_id: &str, options: &CoverOptions) -> Option<Vec<u8>> {
+    fs::read(book_path(cache_path, book_id).join(entry_name(options)))
+        .await
+        .ok()
+}
+
+pub async fn get_original_cover(cache_path: &str, book_id: &str) -> Option<Vec<u8>> {
+    fs::read(book_path(cache_path, book_id).join(ORIGINAL_ENTRY))
+        .await
+        .ok()
+}
+
+pub async fn add_cover(cache_path: &str, book_id: &str, options: &CoverOptions, cover: &[u8]) -> () {
+    add_entry(cache_path, book_id, &entry_name(options), cover).await;
+}
+
+pub async fn add_original_cover(cache_path: &str, book_id: &str, cover: &[u8]) -> () {
+    add_entry(cache_path, book_id, ORIGINAL_ENTRY, cover).await;
+}
+
+async fn add_entry(cache_path: &str, book_id: &str, file_name: &str, cover: &[u8]) -> () {
+    let path = book_path(cache_path, book_id).join(file_name);
+    let temp_path = path.with_extension(format!("{}.tmp", rand::rng().next_u64()));
+
+    let result = async {
+        fs::create_dir_all(book_path(cache_path, book_id)).await?;
+        fs::write(&temp_path, cover).await?;
+        fs::rename(&temp_path, &path).await
+    }
+    .await;
+
+    if let Err(e) = result {
+        warn!("Failed to cache cover for book {book_id}: {e}");
+        let _ = fs::remove_file(&temp_path).await;
+        return;
+    }
+
+    if let Err(e) = evict_covers(cache_path, book_id).await {
+        warn!("Failed to evict cached covers for book {book_id}: {e}");
+    }
+}
+
+// Every size a device asks for is cached on its own, so only the most recently cached covers
+// of a book are kept. Otherwise requesting size after size would fill the disk.
+async fn evict_covers(cache_path: &str, book_id: &str) -> io::Result<()> {
+    let mut entries = Vec::new();
+    let mut dir = fs::read_dir(book_path(cache_path, book_id)).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_some_and(|extension| extension == "jpg") {
+            entries.push((entry.metadata().await?.modified()?, path));
+        }
+    }
+
+    entries.sort();
+    let excess = entries.len().saturating_sub(MAX_CACHED_COVERS);
+    for (_, path) in entries.into_iter().take(excess) {
+        match fs::remove_file(path).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn delete_covers(cache_path: &str, book_id: &str) -> () {
+    match fs::remove_dir_all(book_path(cache_path, book_id)).await {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            warn!("Failed to invalidate cached covers for book {book_id}: {e}");
+        }
+        _ => (),
+    }
+}
+
+fn book_path(cache_path: &str, book_id: &str) -> PathBuf {
+    PathBuf::from(cache_path).join(book_id)
+}
+
+fn entry_name(options: &CoverOptions) -> String {
+    let mode = if options.greyscale { "grey" } else { "color" };
+    format!(
+        "{}x{}_q{}_{mode}.jpg",
+        options.width, options.height, options.quality
+    )
+}
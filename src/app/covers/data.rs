@@ -1,16 +1,23 @@
 use crate::app::covers::models::{CoverToken, CoverTokenError};
 use sqlx::SqlitePool;
 
-pub async fn add_token(pool: &SqlitePool, book_id: &str, token: &str, device_id: &str) -> () {
+pub async fn add_token(
+    pool: &SqlitePool,
+    book_id: &str,
+    token: &str,
+    device_id: &str,
+    expiration: i64,
+) -> () {
     sqlx::query(
         r"
-        INSERT INTO cover_tokens (book_id, token, device_id)
-        VALUES ($1, $2, $3)
+        INSERT INTO cover_tokens (book_id, token, device_id, expiration)
+        VALUES ($1, $2, $3, $4)
         ",
     )
     .bind(book_id)
     .bind(token)
     .bind(device_id)
+    .bind(expiration)
     .execute(pool)
     .await
     .expect("Failed to add cover token");
@@ -19,7 +26,7 @@ pub async fn add_token(pool: &SqlitePool, book_id: &str, token: &str, device_id:
 pub async fn verify_token(pool: &SqlitePool, token: &str) -> Result<CoverToken, CoverTokenError> {
     let token: CoverToken = sqlx::query_as(
         r"
-        SELECT book_id, device_id
+        SELECT book_id, token, device_id, expiration
         FROM cover_tokens
         WHERE token = $1
         ",
@@ -31,12 +38,14 @@ pub async fn verify_token(pool: &SqlitePool, token: &str) -> Result<CoverToken,
     Ok(token)
 }
 
-pub async fn get_token(pool: &SqlitePool, device_id: &str, book_id: &str) -> Option<String> {
-    sqlx::query_scalar(
+pub async fn get_token(pool: &SqlitePool, device_id: &str, book_id: &str) -> Option<CoverToken> {
+    sqlx::query_as(
         r"
-        SELECT token
+        SELECT book_id, token, device_id, expiration
         FROM cover_tokens
         WHERE device_id = $1 AND book_id = $2
+        ORDER BY expiration DESC
+        LIMIT 1
         ",
     )
     .bind(device_id)
@@ -46,6 +55,23 @@ pub async fn get_token(pool: &SqlitePool, device_id: &str, book_id: &str) -> Opt
     .expect("Failed to fetch cover token")
 }
 
+pub async fn get_expiring_books(pool: &SqlitePool, device_id: &str, deadline: i64) -> Vec<String> {
+    sqlx::query_scalar(
+        r"
+        SELECT book_id
+        FROM cover_tokens
+        WHERE device_id = $1
+        GROUP BY book_id
+        HAVING MIN(expiration) <= $2
+        ",
+    )
+    .bind(device_id)
+    .bind(deadline)
+    .fetch_all(pool)
+    .await
+    .expect("Failed to fetch expiring cover tokens")
+}
+
 pub async fn delete_token(pool: &SqlitePool, book_id: &str, device_id: &str) -> () {
     sqlx::query(
         r"
@@ -59,3 +85,60 @@ pub async fn delete_token(pool: &SqlitePool, book_id: &str, device_id: &str) ->
     .await
     .expect("Failed to delete cover token");
 }
+
+pub async fn delete_older_tokens(pool: &SqlitePool, book_id: &str, device_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM cover_tokens
+        WHERE book_id = $1 AND device_id = $2 AND expiration < (
+            SELECT MAX(expiration)
+            FROM cover_tokens
+            WHERE book_id = $1 AND device_id = $2
+        )
+        ",
+    )
+    .bind(book_id)
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete older cover tokens");
+}
+
+pub async fn revoke_token(pool: &SqlitePool, token: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM cover_tokens
+        WHERE token = $1
+        ",
+    )
+    .bind(token)
+    .execute(pool)
+    .await
+    .expect("Failed to revoke cover token");
+}
+
+pub async fn delete_book_tokens(pool: &SqlitePool, book_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM cover_tokens
+        WHERE book_id = $1
+        ",
+    )
+    .bind(book_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete book cover tokens");
+}
+
+pub async fn delete_device_tokens(pool: &SqlitePool, device_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM cover_tokens
+        WHERE device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete device cover tokens");
+}
@@ -1,32 +1,101 @@
 use super::service;
-use crate::app::{AppState, covers::models::CoverTokenError, error::KoboError};
+use crate::app::{
+    AppState,
+    covers::models::{COVER_CONTENT_TYPE, CoverOptions, CoverPathParams, CoverTokenError},
+    error::KoboError,
+};
 use axum::{
     extract::{Path, Query, State},
-    response::IntoResponse,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
 };
-use log::warn;
 use std::collections::HashMap;
 
 pub async fn download_cover_handler(
     State(state): State<AppState>,
     Path(book_id): Path<String>,
     Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, KoboError> {
     let Some(cover_token) = params.get("token") else {
         return Err(CoverTokenError::InvalidToken.into());
     };
 
-    let mut cover = service::download_cover(&state.pool, &state.prosa_client, &book_id, cover_token).await?;
-
     let width: Option<u32> = params.get("width").and_then(|s| s.parse().ok());
     let height: Option<u32> = params.get("height").and_then(|s| s.parse().ok());
 
-    if let (Some(w), Some(h)) = (width, height) {
-        match service::resize_cover(&cover, w, h) {
-            Ok(c) => cover = c,
-            _ => warn!("Failed to resize image."),
-        }
+    let cover = if let (Some(w), Some(h)) = (width, height) {
+        service::download_resized_cover(
+            &state.pool,
+            &state.prosa_client,
+            &state.config.cover_cache.path,
+            &book_id,
+            cover_token,
+            CoverOptions::new(w, h, None, false),
+        )
+        .await?
+    } else {
+        service::download_cover(
+            &state.pool,
+            &state.prosa_client,
+            &state.config.cover_cache.path,
+            &book_id,
+            cover_token,
+        )
+        .await?
+    };
+
+    Ok(cached_cover_response(
+        cover,
+        &headers,
+        state.config.cover_cache.max_age,
+    ))
+}
+
+pub async fn download_templated_cover_handler(
+    State(state): State<AppState>,
+    Path(params): Path<CoverPathParams>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, KoboError> {
+    let cover = service::download_resized_cover(
+        &state.pool,
+        &state.prosa_client,
+        &state.config.cover_cache.path,
+        &params.book_id,
+        &params.token,
+        CoverOptions::new(params.width, params.height, params.quality, params.greyscale),
+    )
+    .await?;
+
+    Ok(cached_cover_response(
+        cover,
+        &headers,
+        state.config.cover_cache.max_age,
+    ))
+}
+
+fn cached_cover_response(cover: Vec<u8>, request_headers: &HeaderMap, max_age: u64) -> Response {
+    let etag = service::generate_etag(&cover);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(COVER_CONTENT_TYPE));
+    headers.insert(
+        header::ETAG,
+        HeaderValue::from_str(&etag).expect("Failed to create header"),
+    );
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_str(&format!("private, max-age={max_age}")).expect("Failed to create header"),
+    );
+
+    let not_modified = request_headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|h| h.split(',').any(|t| t.trim() == etag));
+
+    if not_modified {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
     }
 
-    Ok(cover)
+    (headers, cover).into_response()
 }
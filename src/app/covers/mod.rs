@@ -1,8 +1,16 @@
+mod cache;
 mod data;
 mod handlers;
 mod models;
+mod placeholder;
 pub mod routes;
 mod service;
 
+pub use service::delete_book_tokens;
+pub use service::delete_cached_covers;
+pub use service::delete_token;
 pub use service::get_token;
+pub use service::prune_tokens;
+pub use service::revoke_device_tokens;
+pub use service::rotate_tokens;
 pub use service::update_token;
@@ -1,7 +1,9 @@
+use serde::Deserialize;
 use sqlx::FromRow;
 use strum_macros::{EnumMessage, EnumProperty};
 
 type SqlxError = sqlx::Error;
+type ImageError = image::ImageError;
 
 #[derive(EnumMessage, EnumProperty, Debug)]
 pub enum CoverTokenError {
@@ -14,10 +16,24 @@ pub enum CoverTokenError {
     InternalError,
 }
 
+#[derive(EnumMessage, EnumProperty, Debug)]
+pub enum CoverError {
+    #[strum(message = "InvalidCover")]
+    #[strum(detailed_message = "The cover image of this book could not be decoded.")]
+    #[strum(props(StatusCode = "422"))]
+    InvalidCover,
+    #[strum(message = "EncodingFailed")]
+    #[strum(detailed_message = "The cover image could not be encoded.")]
+    #[strum(props(StatusCode = "500"))]
+    EncodingFailed,
+}
+
 #[derive(FromRow)]
 pub struct CoverToken {
     pub book_id: String,
+    pub token: String,
     pub device_id: String,
+    pub expiration: i64,
 }
 
 impl From<SqlxError> for CoverTokenError {
@@ -29,4 +45,45 @@ impl From<SqlxError> for CoverTokenError {
     }
 }
 
+impl From<ImageError> for CoverError {
+    fn from(_: ImageError) -> Self {
+        CoverError::InvalidCover
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CoverPathParams {
+    pub book_id: String,
+    pub token: String,
+    pub width: u32,
+    pub height: u32,
+    pub quality: Option<u8>,
+    pub greyscale: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct CoverOptions {
+    pub width: u32,
+    pub height: u32,
+    pub quality: u8,
+    pub greyscale: bool,
+}
+
+impl CoverOptions {
+    pub fn new(width: u32, height: u32, quality: Option<u8>, greyscale: bool) -> Self {
+        CoverOptions {
+            width: width.clamp(1, MAX_COVER_SIZE),
+            height: height.clamp(1, MAX_COVER_SIZE),
+            quality: quality.unwrap_or(DEFAULT_COVER_QUALITY).clamp(1, 100),
+            greyscale,
+        }
+    }
+}
+
 pub const COVER_TOKEN_SIZE: usize = 128;
+pub const DEFAULT_COVER_QUALITY: u8 = 85;
+pub const MAX_COVER_SIZE: u32 = 4096;
+pub const MAX_CACHED_COVERS: usize = 8;
+pub const COVER_CONTENT_TYPE: &str = "image/jpeg";
+pub const PLACEHOLDER_WIDTH: u32 = 600;
+pub const PLACEHOLDER_HEIGHT: u32 = 800;
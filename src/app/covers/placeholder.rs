@@ -0,0 +1,194 @@
+use crate::{
+    app::metadata::{ContributorRole, format_series_number},
+    client::ProsaMetadata,
+};
+use ab_glyph::{Font, FontRef, PxScale, ScaleFont, point};
+use image::{Rgb, RgbImage};
+use sha2::{Digest, Sha256};
+
+const FONT: &[u8] = include_bytes!("assets/DejaVuSerif.ttf");
+
+const BACKGROUNDS: [[u8; 3]; 8] = [
+    [38, 70, 83],
+    [42, 157, 143],
+    [94, 84, 142],
+    [120, 66, 59],
+    [61, 90, 128],
+    [108, 117, 125],
+    [76, 110, 72],
+    [143, 90, 40],
+];
+
+#[allow(clippy::cast_precision_loss)]
+pub fn render_placeholder(metadata: &ProsaMetadata, width: u32, height: u32) -> RgbImage {
+    let font = FontRef::try_from_slice(FONT).expect("Failed to load placeholder font");
+
+    let title = metadata.title.as_deref().unwrap_or("Untitled");
+    let author = metadata.contributors.as_ref().and_then(|contributors| {
+        contributors
+            .iter()
+            .find(|c| ContributorRole::from_prosa(&c.role) == Some(ContributorRole::Author))
+            .or(contributors.first())
+            .map(|c| c.name.as_str())
+    });
+    let series = metadata
+        .series
+        .as_ref()
+        .map(|s| format!("{} #{}", s.title, format_series_number(s.number)));
+
+    // The background is derived from the book's title and author, so the same book always gets the same cover
+    let seed = Sha256::digest(format!("{title}\n{}", author.unwrap_or_default()));
+    let background = Rgb(BACKGROUNDS[seed[0] as usize % BACKGROUNDS.len()]);
+    let foreground = Rgb([245, 241, 232]);
+
+    let mut image = RgbImage::from_pixel(width, height, background);
+
+    let margin = width.min(height) as f32 / 10.0;
+    let max_width = width as f32 - 2.0 * margin;
+    let unit = height as f32 / 100.0;
+
+    draw_border(&mut image, margin / 2.0, (unit / 2.0).max(1.0), foreground);
+
+    let title_scale = PxScale::from(unit * 8.0);
+    let title_lines = wrap_text(&font, title_scale, title, max_width, 5);
+    let mut y = margin + unit * 8.0;
+    for line in &title_lines {
+        draw_centered_line(&mut image, &font, title_scale, line, y, foreground);
+        y += unit * 10.0;
+    }
+
+    if let Some(series) = series {
+        let series_scale = PxScale::from(unit * 4.5);
+        for line in wrap_text(&font, series_scale, &series, max_width, 2) {
+            y += unit * 2.0;
+            draw_centered_line(&mut image, &font, series_scale, &line, y, foreground);
+            y += unit * 4.0;
+        }
+    }
+
+    if let Some(author) = author {
+        let author_scale = PxScale::from(unit * 5.0);
+        let author_lines = wrap_text(&font, author_scale, author, max_width, 2);
+        let mut y = height as f32 - margin - unit * 7.0 * (author_lines.len() as f32 - 1.0);
+        for line in &author_lines {
+            draw_centered_line(&mut image, &font, author_scale, line, y, foreground);
+            y += unit * 7.0;
+        }
+    }
+
+    image
+}
+
+fn wrap_text(font: &FontRef, scale: PxScale, text: &str, max_width: f32, max_lines: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for word in text.split_whitespace() {
+        let candidate = if current.is_empty() {
+            word.to_string()
+        } else {
+            format!("{current} {word}")
+        };
+
+        if current.is_empty() || line_width(font, scale, &candidate) <= max_width {
+            current = candidate;
+        } else {
+            lines.push(current);
+            current = word.to_string();
+        }
+    }
+
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        if let Some(last) = lines.last_mut() {
+            last.push('…');
+        }
+    }
+
+    lines
+}
+
+fn line_width(font: &FontRef, scale: PxScale, text: &str) -> f32 {
+    let font = font.as_scaled(scale);
+    let mut width = 0.0;
+    let mut previous = None;
+
+    for c in text.chars() {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            width += font.kern(previous, id);
+        }
+        width += font.h_advance(id);
+        previous = Some(id);
+    }
+
+    width
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn draw_centered_line(
+    image: &mut RgbImage,
+    font: &FontRef,
+    scale: PxScale,
+    text: &str,
+    y: f32,
+    color: Rgb<u8>,
+) {
+    let scaled_font = font.as_scaled(scale);
+    let mut x = (image.width() as f32 - line_width(font, scale, text)) / 2.0;
+    let mut previous = None;
+
+    for c in text.chars() {
+        let id = scaled_font.glyph_id(c);
+        if let Some(previous) = previous {
+            x += scaled_font.kern(previous, id);
+        }
+
+        let glyph = id.with_scale_and_position(scale, point(x, y));
+        x += scaled_font.h_advance(id);
+        previous = Some(id);
+
+        let Some(outline) = font.outline_glyph(glyph) else {
+            continue;
+        };
+
+        let bounds = outline.px_bounds();
+        outline.draw(|gx, gy, coverage| {
+            let px = bounds.min.x as i64 + i64::from(gx);
+            let py = bounds.min.y as i64 + i64::from(gy);
+
+            if px < 0 || py < 0 || px >= i64::from(image.width()) || py >= i64::from(image.height()) {
+                return;
+            }
+
+            let pixel = image.get_pixel_mut(px as u32, py as u32);
+            for channel in 0..3 {
+                let blended =
+                    f32::from(pixel[channel]) * (1.0 - coverage) + f32::from(color[channel]) * coverage;
+                pixel[channel] = blended.round() as u8;
+            }
+        });
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn draw_border(image: &mut RgbImage, inset: f32, thickness: f32, color: Rgb<u8>) {
+    let (width, height) = (image.width() as f32, image.height() as f32);
+
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let (x, y) = (x as f32, y as f32);
+        let inside_outer = x >= inset && y >= inset && x < width - inset && y < height - inset;
+        let inside_inner = x >= inset + thickness
+            && y >= inset + thickness
+            && x < width - inset - thickness
+            && y < height - inset - thickness;
+
+        if inside_outer && !inside_inner {
+            *pixel = color;
+        }
+    }
+}
@@ -6,6 +6,8 @@ use axum::{Router, extract::DefaultBodyLimit, routing::get};
 pub fn get_routes(state: AppState) -> Router {
     Router::new()
         .route("/images/{book_id}", get(handlers::download_cover_handler))
+        .route("/images/{book_id}/{token}/{width}/{height}/{greyscale}/image.jpg", get(handlers::download_templated_cover_handler))
+        .route("/images/{book_id}/{token}/{width}/{height}/{quality}/{greyscale}/image.jpg", get(handlers::download_templated_cover_handler))
         .layer(DefaultBodyLimit::max(31457280))
         .with_state(state)
 }
@@ -1,65 +1,259 @@
 use crate::{
     app::{
         covers::{
-            data,
-            models::{COVER_TOKEN_SIZE, CoverTokenError},
+            cache, data,
+            models::{
+                COVER_TOKEN_SIZE, CoverError, CoverOptions, CoverTokenError, PLACEHOLDER_HEIGHT,
+                PLACEHOLDER_WIDTH,
+            },
+            placeholder,
         },
         devices,
         error::KoboError,
     },
-    client::prosa::Client,
+    client::{
+        ProsaMetadata,
+        prosa::{Client, ClientError},
+    },
 };
 use base64::{Engine, prelude::BASE64_URL_SAFE};
-use image::{ImageError, imageops::FilterType};
-use image::{ImageFormat, ImageReader};
+use image::{
+    DynamicImage, GenericImageView, ImageReader, Rgb, RgbImage, codecs::jpeg::JpegEncoder,
+    imageops::FilterType,
+};
 use rand::RngCore;
+use sha2::{Digest, Sha256};
 use sqlx::SqlitePool;
-use std::io::Cursor;
+use std::{
+    io::Cursor,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::task;
 
 pub async fn download_cover(
     pool: &SqlitePool,
     client: &Client,
+    cache_path: &str,
     book_id: &str,
     cover_token: &str,
 ) -> Result<Vec<u8>, KoboError> {
     let api_key = verify_token(pool, book_id, cover_token).await?;
-    let cover = client.download_cover(book_id, &api_key)?;
-    Ok(cover)
+
+    if let Some(cover) = cache::get_original_cover(cache_path, book_id).await {
+        return Ok(cover);
+    }
+
+    let cover = match client.download_cover(book_id, &api_key) {
+        Err(ClientError::NotFound) => {
+            let options = CoverOptions::new(PLACEHOLDER_WIDTH, PLACEHOLDER_HEIGHT, None, false);
+            let placeholder = generate_placeholder(client, book_id, &api_key, options).await?;
+            cache::add_original_cover(cache_path, book_id, &placeholder).await;
+            return Ok(placeholder);
+        }
+        result => result?,
+    };
+
+    let normalized = task::spawn_blocking(move || normalize_cover(&cover))
+        .await
+        .expect("Failed to join normalize task")?;
+
+    cache::add_original_cover(cache_path, book_id, &normalized).await;
+
+    Ok(normalized)
 }
 
-pub fn resize_cover(cover: &Vec<u8>, width: u32, height: u32) -> Result<Vec<u8>, ImageError> {
+pub async fn download_resized_cover(
+    pool: &SqlitePool,
+    client: &Client,
+    cache_path: &str,
+    book_id: &str,
+    cover_token: &str,
+    options: CoverOptions,
+) -> Result<Vec<u8>, KoboError> {
+    let api_key = verify_token(pool, book_id, cover_token).await?;
+
+    if let Some(cover) = cache::get_cover(cache_path, book_id, &options).await {
+        return Ok(cover);
+    }
+
+    let cover = match client.download_cover(book_id, &api_key) {
+        Err(ClientError::NotFound) => {
+            let placeholder = generate_placeholder(client, book_id, &api_key, options).await?;
+            cache::add_cover(cache_path, book_id, &options, &placeholder).await;
+            return Ok(placeholder);
+        }
+        result => result?,
+    };
+
+    let resized = task::spawn_blocking(move || resize_cover(&cover, &options))
+        .await
+        .expect("Failed to join resize task")?;
+
+    cache::add_cover(cache_path, book_id, &options, &resized).await;
+
+    Ok(resized)
+}
+
+pub async fn delete_cached_covers(cache_path: &str, book_id: &str) -> () {
+    cache::delete_covers(cache_path, book_id).await;
+}
+
+pub fn generate_etag(cover: &[u8]) -> String {
+    let digest = Sha256::digest(cover);
+    format!("\"{}\"", BASE64_URL_SAFE.encode(&digest[..16]))
+}
+
+async fn generate_placeholder(
+    client: &Client,
+    book_id: &str,
+    api_key: &str,
+    options: CoverOptions,
+) -> Result<Vec<u8>, KoboError> {
+    // Makes sure the book itself exists before rendering a cover for it
+    client.fetch_book_file_metadata(book_id, api_key)?;
+
+    let metadata = match client.fetch_metadata(book_id, api_key) {
+        Ok(response) => response,
+        Err(ClientError::NotFound) => ProsaMetadata::default(),
+        Err(e) => return Err(e.into()),
+    };
+
+    let placeholder = task::spawn_blocking(move || {
+        let image = placeholder::render_placeholder(&metadata, options.width, options.height);
+        encode_cover(&DynamicImage::ImageRgb8(image), &options)
+    })
+    .await
+    .expect("Failed to join placeholder task")?;
+
+    Ok(placeholder)
+}
+
+fn normalize_cover(cover: &[u8]) -> Result<Vec<u8>, CoverError> {
+    let image = decode_cover(cover)?;
+    let (width, height) = image.dimensions();
+
+    encode_cover(&image, &CoverOptions::new(width, height, None, false))
+}
+
+fn resize_cover(cover: &[u8], options: &CoverOptions) -> Result<Vec<u8>, CoverError> {
+    let image = decode_cover(cover)?;
+    let (width, height) = image.dimensions();
+
+    // Covers are never upscaled, so a request for a large size can't make the cache store more than the source
+    let image = image.resize(
+        options.width.min(width),
+        options.height.min(height),
+        FilterType::Lanczos3,
+    );
+
+    encode_cover(&image, options)
+}
+
+fn decode_cover(cover: &[u8]) -> Result<DynamicImage, CoverError> {
     let image = ImageReader::new(Cursor::new(cover))
-        .with_guessed_format()?
-        .decode()?
-        .resize_exact(width, height, FilterType::Nearest);
+        .with_guessed_format()
+        .map_err(|_| CoverError::InvalidCover)?
+        .decode()?;
+
+    Ok(image)
+}
 
-    let mut output = Cursor::new(Vec::new());
-    image.write_to(&mut output, ImageFormat::Jpeg)?;
+fn encode_cover(image: &DynamicImage, options: &CoverOptions) -> Result<Vec<u8>, CoverError> {
+    let flattened = DynamicImage::ImageRgb8(flatten_cover(image));
+
+    let image = if options.greyscale {
+        DynamicImage::ImageLuma8(flattened.to_luma8())
+    } else {
+        flattened
+    };
 
-    Ok(output.into_inner())
+    // The default JPEG encoder produces baseline images, which every Kobo firmware can render
+    let mut output = Vec::new();
+    let encoder = JpegEncoder::new_with_quality(&mut output, options.quality);
+    image
+        .write_with_encoder(encoder)
+        .map_err(|_| CoverError::EncodingFailed)?;
+
+    Ok(output)
+}
+
+fn flatten_cover(image: &DynamicImage) -> RgbImage {
+    let rgba = image.to_rgba8();
+
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let alpha = f32::from(a) / 255.0;
+        let blend = |c: u8| (f32::from(c) * alpha + 255.0 * (1.0 - alpha)).round() as u8;
+
+        Rgb([blend(r), blend(g), blend(b)])
+    })
 }
 
-pub async fn get_token(pool: &SqlitePool, book_id: &str, device_id: &str) -> String {
+/// Returns the newest token of a book. Once it is past half of its lifetime a new one is issued next to it,
+/// so the device keeps a working cover ID until it receives the new one.
+pub async fn get_token(pool: &SqlitePool, book_id: &str, expiration: i64, device_id: &str) -> String {
+    let deadline = current_timestamp() + expiration / 2;
+
     match data::get_token(pool, device_id, book_id).await {
-        Some(token) => token,
-        None => update_token(pool, book_id, device_id).await,
+        Some(token) if token.expiration > deadline => token.token,
+        _ => add_token(pool, book_id, expiration, device_id).await,
+    }
+}
+
+pub async fn update_token(pool: &SqlitePool, book_id: &str, expiration: i64, device_id: &str) -> String {
+    data::delete_token(pool, book_id, device_id).await;
+    add_token(pool, book_id, expiration, device_id).await
+}
+
+pub async fn rotate_tokens(pool: &SqlitePool, expiration: i64, device_id: &str) -> Vec<String> {
+    // Tokens are rotated once past half of their lifetime, so a device that syncs
+    // regularly is handed a new cover ID before the old one stops working.
+    // Rotation only happens here, during sync, so idle devices keep their tokens until they expire.
+    // The old tokens are only pruned once the sync handing out the new ones is built, so books
+    // keep being returned here until a sync succeeds.
+    let deadline = current_timestamp() + expiration / 2;
+    data::get_expiring_books(pool, device_id, deadline).await
+}
+
+/// Deletes every token of the books but the newest one, once the device received it.
+pub async fn prune_tokens(pool: &SqlitePool, book_ids: &[String], device_id: &str) -> () {
+    for book_id in book_ids {
+        data::delete_older_tokens(pool, book_id, device_id).await;
     }
 }
 
-pub async fn update_token(pool: &SqlitePool, book_id: &str, device_id: &str) -> String {
+async fn add_token(pool: &SqlitePool, book_id: &str, expiration: i64, device_id: &str) -> String {
     let mut bytes = vec![0u8; COVER_TOKEN_SIZE];
     rand::rng().fill_bytes(&mut bytes);
     let token = BASE64_URL_SAFE.encode(bytes);
 
-    data::delete_token(pool, book_id, device_id).await;
-    data::add_token(pool, book_id, &token, device_id).await;
+    let expiration = current_timestamp() + expiration;
+
+    data::add_token(pool, book_id, &token, device_id, expiration).await;
 
     token
 }
 
+pub async fn delete_token(pool: &SqlitePool, book_id: &str, device_id: &str) -> () {
+    data::delete_token(pool, book_id, device_id).await;
+}
+
+pub async fn delete_book_tokens(pool: &SqlitePool, book_id: &str) -> () {
+    data::delete_book_tokens(pool, book_id).await;
+}
+
+pub async fn revoke_device_tokens(pool: &SqlitePool, device_id: &str) -> () {
+    data::delete_device_tokens(pool, device_id).await;
+}
+
 async fn verify_token(pool: &SqlitePool, book_id: &str, token: &str) -> Result<String, CoverTokenError> {
     let verifier = data::verify_token(pool, token).await?;
 
+    if current_timestamp() > verifier.expiration {
+        data::revoke_token(pool, token).await;
+        return Err(CoverTokenError::InvalidToken);
+    }
+
     if verifier.book_id != book_id {
         return Err(CoverTokenError::InvalidToken);
     }
@@ -71,3 +265,12 @@ async fn verify_token(pool: &SqlitePool, book_id: &str, token: &str) -> Result<S
 
     Ok(api_key)
 }
+
+fn current_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Failed to get time since epoch")
+        .as_secs()
+        .try_into()
+        .expect("Failed to convert timestamp")
+}
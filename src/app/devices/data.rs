@@ -1,4 +1,4 @@
-use super::models::{DeviceError, LinkedDevice, UnlinkedDevice};
+use super::models::{DeviceActivity, DeviceError, LinkedDevice, ShelfShare, UnlinkedDevice};
 use sqlx::SqlitePool;
 
 pub async fn add_unlinked_device(pool: &SqlitePool, device_id: &str, timestamp: i64) -> () {
@@ -134,3 +134,246 @@ pub async fn get_linked_devices(pool: &SqlitePool, api_key: &str) -> Vec<String>
 
     devices
 }
+
+pub async fn add_activity(
+    pool: &SqlitePool,
+    device_id: &str,
+    timestamp: i64,
+    event: &str,
+    book_id: &str,
+    annotation_id: &str,
+    detail: Option<&str>,
+) -> () {
+    sqlx::query(
+        r"
+        INSERT INTO device_activity (device_id, timestamp, event, book_id, annotation_id, detail)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ",
+    )
+    .bind(device_id)
+    .bind(timestamp)
+    .bind(event)
+    .bind(book_id)
+    .bind(annotation_id)
+    .bind(detail)
+    .execute(pool)
+    .await
+    .expect("Failed to add device activity");
+}
+
+pub async fn get_activity(pool: &SqlitePool, device_id: &str) -> Vec<DeviceActivity> {
+    sqlx::query_as(
+        r"
+        SELECT timestamp, event, book_id, annotation_id, detail
+        FROM device_activity
+        WHERE device_id = $1
+        ORDER BY timestamp DESC
+        ",
+    )
+    .bind(device_id)
+    .fetch_all(pool)
+    .await
+    .expect("Failed to get device activity")
+}
+
+pub async fn delete_activity(pool: &SqlitePool, device_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM device_activity
+        WHERE device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete device activity");
+}
+
+pub async fn set_smart_shelf_rules(pool: &SqlitePool, device_id: &str, rules: &str) -> () {
+    sqlx::query(
+        r"
+        INSERT INTO smart_shelf_rules (device_id, rules)
+        VALUES ($1, $2)
+        ON CONFLICT(device_id) DO UPDATE SET rules = excluded.rules
+        ",
+    )
+    .bind(device_id)
+    .bind(rules)
+    .execute(pool)
+    .await
+    .expect("Failed to set smart shelf rules");
+}
+
+pub async fn get_smart_shelf_rules(pool: &SqlitePool, device_id: &str) -> Option<String> {
+    sqlx::query_scalar(
+        r"
+        SELECT rules
+        FROM smart_shelf_rules
+        WHERE device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .fetch_optional(pool)
+    .await
+    .expect("Failed to get smart shelf rules")
+}
+
+pub async fn delete_smart_shelf_rules(pool: &SqlitePool, device_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM smart_shelf_rules
+        WHERE device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete smart shelf rules");
+}
+
+pub async fn set_shelf_shares(pool: &SqlitePool, owner_device_id: &str, shares: &[(&str, &str)]) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM shelf_shares
+        WHERE owner_device_id = $1
+        ",
+    )
+    .bind(owner_device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete shelf shares");
+
+    for (shelf_id, user_id) in shares {
+        sqlx::query(
+            r"
+            INSERT OR IGNORE INTO shelf_shares (owner_device_id, shelf_id, user_id)
+            VALUES ($1, $2, $3)
+            ",
+        )
+        .bind(owner_device_id)
+        .bind(shelf_id)
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .expect("Failed to add shelf share");
+    }
+}
+
+pub async fn get_shelf_shares(pool: &SqlitePool, owner_device_id: &str) -> Vec<(String, String)> {
+    sqlx::query_as(
+        r"
+        SELECT shelf_id, user_id
+        FROM shelf_shares
+        WHERE owner_device_id = $1
+        ORDER BY shelf_id, user_id
+        ",
+    )
+    .bind(owner_device_id)
+    .fetch_all(pool)
+    .await
+    .expect("Failed to get shelf shares")
+}
+
+// Owners are resolved through their linked device, so unlinking it stops sharing its shelves
+pub async fn is_shelf_shared(pool: &SqlitePool, shelf_id: &str, user_id: &str) -> bool {
+    sqlx::query_scalar(
+        r"
+        SELECT EXISTS (
+            SELECT 1
+            FROM shelf_shares s
+            JOIN linked_devices d ON d.device_id = s.owner_device_id
+            WHERE s.shelf_id = $1 AND s.user_id = $2
+        )
+        ",
+    )
+    .bind(shelf_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+    .expect("Failed to check shelf share")
+}
+
+pub async fn set_accepted_shelf_shares(pool: &SqlitePool, device_id: &str, shares: &[(&str, &str)]) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM accepted_shelf_shares
+        WHERE device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete accepted shelf shares");
+
+    for (shelf_id, user_id) in shares {
+        sqlx::query(
+            r"
+            INSERT OR REPLACE INTO accepted_shelf_shares (device_id, shelf_id, user_id)
+            VALUES ($1, $2, $3)
+            ",
+        )
+        .bind(device_id)
+        .bind(shelf_id)
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .expect("Failed to add accepted shelf share");
+    }
+}
+
+pub async fn get_accepted_shelf_shares(pool: &SqlitePool, device_id: &str) -> Vec<(String, String)> {
+    sqlx::query_as(
+        r"
+        SELECT shelf_id, user_id
+        FROM accepted_shelf_shares
+        WHERE device_id = $1
+        ORDER BY shelf_id
+        ",
+    )
+    .bind(device_id)
+    .fetch_all(pool)
+    .await
+    .expect("Failed to get accepted shelf shares")
+}
+
+// Only shares the device accepted, as the user they were shared with, are mirrored
+pub async fn get_device_shelf_shares(pool: &SqlitePool, device_id: &str) -> Vec<ShelfShare> {
+    sqlx::query_as(
+        r"
+        SELECT s.shelf_id, MIN(d.api_key) AS owner_api_key
+        FROM accepted_shelf_shares a
+        JOIN shelf_shares s ON s.shelf_id = a.shelf_id AND s.user_id = a.user_id
+        JOIN linked_devices d ON d.device_id = s.owner_device_id
+        WHERE a.device_id = $1
+        GROUP BY s.shelf_id
+        ",
+    )
+    .bind(device_id)
+    .fetch_all(pool)
+    .await
+    .expect("Failed to get device shelf shares")
+}
+
+pub async fn delete_shelf_shares(pool: &SqlitePool, device_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM shelf_shares
+        WHERE owner_device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete shelf shares");
+
+    sqlx::query(
+        r"
+        DELETE FROM accepted_shelf_shares
+        WHERE device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete accepted shelf shares");
+}
@@ -1,6 +1,7 @@
 use super::{
     models::{
-        DeviceAuthRequest, DeviceAuthResponse, LinkDeviceRequest, RefreshTokenRequest, RefreshTokenResponse,
+        AcceptedShelves, DeviceAuthRequest, DeviceAuthResponse, LinkDeviceRequest, RefreshTokenRequest,
+        RefreshTokenResponse, SharedShelves, SmartShelfRules,
     },
     service,
 };
@@ -8,7 +9,7 @@ use crate::app::{AppState, Pool, authentication, devices::models::DeviceError, e
 use axum::{
     Json,
     extract::{Path, State},
-    http::HeaderMap,
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
 };
 
@@ -91,3 +92,105 @@ pub async fn unlink_device_handler(
     service::unlink_device(&pool, &device_id, api_key).await?;
     Ok(())
 }
+
+pub async fn get_device_activity_handler(
+    State(pool): State<Pool>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+) -> Result<impl IntoResponse, KoboError> {
+    let api_key = headers
+        .get("api-key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(DeviceError::MissingApiKey)?;
+
+    let activity = service::get_activity(&pool, &device_id, api_key).await?;
+    Ok(Json(activity))
+}
+
+pub async fn get_smart_shelf_rules_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+) -> Result<impl IntoResponse, KoboError> {
+    let api_key = headers
+        .get("api-key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(DeviceError::MissingApiKey)?;
+
+    let rules =
+        service::get_smart_shelf_rules(&state.pool, &state.config.smart_shelves, &device_id, api_key).await?;
+    Ok(Json(rules))
+}
+
+pub async fn set_smart_shelf_rules_handler(
+    State(pool): State<Pool>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+    Json(body): Json<SmartShelfRules>,
+) -> Result<impl IntoResponse, KoboError> {
+    let api_key = headers
+        .get("api-key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(DeviceError::MissingApiKey)?;
+
+    service::set_smart_shelf_rules(&pool, &device_id, &body, api_key).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_shared_shelves_handler(
+    State(pool): State<Pool>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+) -> Result<impl IntoResponse, KoboError> {
+    let api_key = headers
+        .get("api-key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(DeviceError::MissingApiKey)?;
+
+    let shared = service::get_shared_shelves(&pool, &device_id, api_key).await?;
+    Ok(Json(shared))
+}
+
+pub async fn set_shared_shelves_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+    Json(body): Json<SharedShelves>,
+) -> Result<impl IntoResponse, KoboError> {
+    let api_key = headers
+        .get("api-key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(DeviceError::MissingApiKey)?;
+
+    service::set_shared_shelves(&state.pool, &state.prosa_client, &device_id, &body, api_key).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_accepted_shelves_handler(
+    State(pool): State<Pool>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+) -> Result<impl IntoResponse, KoboError> {
+    let api_key = headers
+        .get("api-key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(DeviceError::MissingApiKey)?;
+
+    let accepted = service::get_accepted_shelves(&pool, &device_id, api_key).await?;
+    Ok(Json(accepted))
+}
+
+pub async fn set_accepted_shelves_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+    Json(body): Json<AcceptedShelves>,
+) -> Result<impl IntoResponse, KoboError> {
+    let api_key = headers
+        .get("api-key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(DeviceError::MissingApiKey)?;
+
+    service::set_accepted_shelves(&state.pool, &state.prosa_client, &device_id, &body, api_key).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
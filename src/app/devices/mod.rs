@@ -3,3 +3,5 @@ mod handlers;
 mod models;
 pub mod routes;
 pub mod service;
+
+pub use models::{ActivityEvent, ShelfShare};
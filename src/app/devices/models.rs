@@ -1,10 +1,11 @@
+use crate::config::SmartShelfRule;
 use serde::{Deserialize, Serialize};
 use sqlx::{
     error::{DatabaseError, ErrorKind},
     prelude::FromRow,
     sqlite::SqliteError,
 };
-use strum_macros::{EnumMessage, EnumProperty};
+use strum_macros::{AsRefStr, EnumMessage, EnumProperty};
 
 type SqlxError = sqlx::Error;
 
@@ -30,6 +31,10 @@ pub enum DeviceError {
     #[strum(detailed_message = "The api key must be provided.")]
     #[strum(props(StatusCode = "400"))]
     MissingApiKey,
+    #[strum(message = "ShelfShareNotFound")]
+    #[strum(detailed_message = "The shelf is not shared with this user.")]
+    #[strum(props(StatusCode = "404"))]
+    ShelfShareNotFound,
     #[strum(message = "Internal error")]
     #[strum(props(StatusCode = "500"))]
     InternalError,
@@ -47,6 +52,62 @@ pub struct LinkedDevice {
     pub api_key: String,
 }
 
+#[derive(AsRefStr, Clone, Copy, Debug)]
+pub enum ActivityEvent {
+    // The device sent an annotation older than the one already stored
+    StaleAnnotationUpdate,
+    // The device sent an annotation that was deleted more recently elsewhere
+    DeletedAnnotationUpdate,
+    // The device sent an annotation with a location Prosa can't store
+    InvalidAnnotationLocation,
+}
+
+#[derive(Serialize, FromRow)]
+pub struct DeviceActivity {
+    pub timestamp: i64,
+    pub event: String,
+    pub book_id: String,
+    pub annotation_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SmartShelfRules {
+    pub rules: Vec<SmartShelfRule>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SharedShelves {
+    pub shelves: Vec<SharedShelf>,
+}
+
+// Shelves are shared with Prosa users, by their user ID
+#[derive(Serialize, Deserialize)]
+pub struct SharedShelf {
+    pub shelf_id: String,
+    pub shared_with: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AcceptedShelves {
+    pub shelves: Vec<AcceptedShelf>,
+}
+
+// A share accepted by a device, as the Prosa user it was shared with
+#[derive(Serialize, Deserialize, FromRow)]
+pub struct AcceptedShelf {
+    pub shelf_id: String,
+    pub user_id: String,
+}
+
+// A shelf shared with a device, together with the API key of the device that shares it
+#[derive(FromRow)]
+pub struct ShelfShare {
+    pub shelf_id: String,
+    pub owner_api_key: String,
+}
+
 impl From<SqlxError> for DeviceError {
     fn from(error: SqlxError) -> Self {
         match error {
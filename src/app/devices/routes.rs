@@ -2,7 +2,7 @@ use super::handlers;
 use crate::app::AppState;
 use axum::{
     Router,
-    routing::{delete, get, post},
+    routing::{delete, get, post, put},
 };
 
 #[rustfmt::skip]
@@ -12,6 +12,13 @@ pub fn get_routes(state: AppState) -> Router {
         .route("/devices/linked", get(handlers::get_linked_devices_handler))
         .route("/devices/linked", post(handlers::link_device_handler))
         .route("/devices/linked/{device_id}", delete(handlers::unlink_device_handler))
+        .route("/devices/linked/{device_id}/activity", get(handlers::get_device_activity_handler))
+        .route("/devices/linked/{device_id}/smart-shelves", get(handlers::get_smart_shelf_rules_handler))
+        .route("/devices/linked/{device_id}/smart-shelves", put(handlers::set_smart_shelf_rules_handler))
+        .route("/devices/linked/{device_id}/shared-shelves", get(handlers::get_shared_shelves_handler))
+        .route("/devices/linked/{device_id}/shared-shelves", put(handlers::set_shared_shelves_handler))
+        .route("/devices/linked/{device_id}/accepted-shelves", get(handlers::get_accepted_shelves_handler))
+        .route("/devices/linked/{device_id}/accepted-shelves", put(handlers::set_accepted_shelves_handler))
         .route("/v1/auth/device", post(handlers::device_auth_handler))
         .route("/v1/auth/refresh", post(handlers::refresh_token_handler))
         .with_state(state)
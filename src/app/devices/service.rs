@@ -1,8 +1,14 @@
 use super::{
     data,
-    models::{DeviceError, LinkedDevice, UnlinkedDevice},
+    models::{
+        AcceptedShelf, AcceptedShelves, ActivityEvent, DeviceActivity, DeviceError, LinkedDevice,
+        SharedShelf, SharedShelves, ShelfShare, SmartShelfRules, UnlinkedDevice,
+    },
+};
+use crate::{
+    app::{ProsaClient, categories, covers, error::KoboError, metadata, shelves},
+    config::{SmartShelfRule, SmartShelves},
 };
-use crate::app::error::KoboError;
 use base64::{Engine, prelude::BASE64_URL_SAFE};
 use sha2::{Digest, Sha256};
 use sqlx::SqlitePool;
@@ -52,6 +58,13 @@ pub async fn unlink_device(pool: &SqlitePool, device_id: &str, api_key: &str) ->
 
     data::remove_linked_device(pool, device_id, api_key).await?;
     data::add_unlinked_device(pool, device_id, now).await;
+    covers::revoke_device_tokens(pool, device_id).await;
+    categories::delete_device_categories(pool, device_id).await;
+    metadata::service::delete_pending_overrides(pool, device_id).await;
+    data::delete_activity(pool, device_id).await;
+    data::delete_smart_shelf_rules(pool, device_id).await;
+    data::delete_shelf_shares(pool, device_id).await;
+    shelves::delete_device_shelves(pool, device_id).await;
 
     Ok(())
 }
@@ -64,6 +77,183 @@ pub async fn get_linked_devices(pool: &SqlitePool, api_key: &str) -> Result<Vec<
     Ok(data::get_linked_devices(pool, api_key).await)
 }
 
+pub async fn get_activity(
+    pool: &SqlitePool,
+    device_id: &str,
+    api_key: &str,
+) -> Result<Vec<DeviceActivity>, KoboError> {
+    verify_device_owner(pool, device_id, api_key).await?;
+
+    Ok(data::get_activity(pool, device_id).await)
+}
+
+pub async fn add_activity(
+    pool: &SqlitePool,
+    device_id: &str,
+    event: ActivityEvent,
+    book_id: &str,
+    annotation_id: &str,
+    detail: Option<&str>,
+) -> () {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs() as i64;
+
+    data::add_activity(
+        pool,
+        device_id,
+        now,
+        event.as_ref(),
+        book_id,
+        annotation_id,
+        detail,
+    )
+    .await;
+}
+
+pub async fn get_smart_shelf_rules(
+    pool: &SqlitePool,
+    config: &SmartShelves,
+    device_id: &str,
+    api_key: &str,
+) -> Result<SmartShelfRules, KoboError> {
+    verify_device_owner(pool, device_id, api_key).await?;
+
+    let rules = get_device_smart_shelf_rules(pool, config, device_id).await;
+    Ok(SmartShelfRules { rules })
+}
+
+pub async fn set_smart_shelf_rules(
+    pool: &SqlitePool,
+    device_id: &str,
+    rules: &SmartShelfRules,
+    api_key: &str,
+) -> Result<(), KoboError> {
+    verify_device_owner(pool, device_id, api_key).await?;
+
+    let rules = serde_json::to_string(&rules.rules).expect("Failed to serialize smart shelf rules");
+    data::set_smart_shelf_rules(pool, device_id, &rules).await;
+
+    Ok(())
+}
+
+/// Returns the smart shelf rules of a device, falling back to the configured ones.
+pub async fn get_device_smart_shelf_rules(
+    pool: &SqlitePool,
+    config: &SmartShelves,
+    device_id: &str,
+) -> Vec<SmartShelfRule> {
+    match data::get_smart_shelf_rules(pool, device_id).await {
+        Some(rules) => serde_json::from_str(&rules).expect("Failed to deserialize smart shelf rules"),
+        None => config.rules.clone(),
+    }
+}
+
+pub async fn get_shared_shelves(
+    pool: &SqlitePool,
+    device_id: &str,
+    api_key: &str,
+) -> Result<SharedShelves, KoboError> {
+    verify_device_owner(pool, device_id, api_key).await?;
+
+    let mut shelves: Vec<SharedShelf> = Vec::new();
+    for (shelf_id, shared_with) in data::get_shelf_shares(pool, device_id).await {
+        match shelves.last_mut() {
+            Some(shelf) if shelf.shelf_id == shelf_id => shelf.shared_with.push(shared_with),
+            _ => shelves.push(SharedShelf {
+                shelf_id,
+                shared_with: vec![shared_with],
+            }),
+        }
+    }
+
+    Ok(SharedShelves { shelves })
+}
+
+/// Replaces the shelves a device shares with other Prosa users. Only the owner of a shelf can share it,
+/// and sharing stops once the device is unlinked.
+pub async fn set_shared_shelves(
+    pool: &SqlitePool,
+    client: &ProsaClient,
+    device_id: &str,
+    shelves: &SharedShelves,
+    api_key: &str,
+) -> Result<(), KoboError> {
+    verify_device_owner(pool, device_id, api_key).await?;
+
+    for shelf in &shelves.shelves {
+        let metadata = client.get_shelf_metadata(&shelf.shelf_id, api_key)?;
+        client.verify_user(&metadata.owner_id, api_key)?;
+    }
+
+    let shares: Vec<(&str, &str)> = shelves
+        .shelves
+        .iter()
+        .flat_map(|shelf| {
+            shelf
+                .shared_with
+                .iter()
+                .map(|user_id| (shelf.shelf_id.as_str(), user_id.as_str()))
+        })
+        .collect();
+
+    data::set_shelf_shares(pool, device_id, &shares).await;
+
+    Ok(())
+}
+
+pub async fn get_accepted_shelves(
+    pool: &SqlitePool,
+    device_id: &str,
+    api_key: &str,
+) -> Result<AcceptedShelves, KoboError> {
+    verify_device_owner(pool, device_id, api_key).await?;
+
+    let shelves = data::get_accepted_shelf_shares(pool, device_id)
+        .await
+        .into_iter()
+        .map(|(shelf_id, user_id)| AcceptedShelf { shelf_id, user_id })
+        .collect();
+
+    Ok(AcceptedShelves { shelves })
+}
+
+/// Replaces the shared shelves a device mirrors. Each share is accepted as the Prosa user it was
+/// shared with, so the device's API key must act as that user.
+pub async fn set_accepted_shelves(
+    pool: &SqlitePool,
+    client: &ProsaClient,
+    device_id: &str,
+    shelves: &AcceptedShelves,
+    api_key: &str,
+) -> Result<(), KoboError> {
+    verify_device_owner(pool, device_id, api_key).await?;
+
+    for shelf in &shelves.shelves {
+        client.verify_user(&shelf.user_id, api_key)?;
+
+        if !data::is_shelf_shared(pool, &shelf.shelf_id, &shelf.user_id).await {
+            return Err(DeviceError::ShelfShareNotFound.into());
+        }
+    }
+
+    let shares: Vec<(&str, &str)> = shelves
+        .shelves
+        .iter()
+        .map(|shelf| (shelf.shelf_id.as_str(), shelf.user_id.as_str()))
+        .collect();
+
+    data::set_accepted_shelf_shares(pool, device_id, &shares).await;
+
+    Ok(())
+}
+
+/// Returns the shelves a device accepted, with the API keys of the devices sharing them.
+pub async fn get_device_shelf_shares(pool: &SqlitePool, device_id: &str) -> Vec<ShelfShare> {
+    data::get_device_shelf_shares(pool, device_id).await
+}
+
 pub async fn get_linked_device(pool: &SqlitePool, device_id: &str) -> Option<LinkedDevice> {
     data::get_linked_device(pool, device_id).await
 }
@@ -77,6 +267,17 @@ pub fn generate_device_id(device_id: &str, user_key: &str) -> String {
     BASE64_URL_SAFE.encode(digest)
 }
 
+async fn verify_device_owner(pool: &SqlitePool, device_id: &str, api_key: &str) -> Result<(), DeviceError> {
+    if !is_valid_api_key(api_key) {
+        return Err(DeviceError::InvalidApiKey);
+    }
+
+    match data::get_linked_device(pool, device_id).await {
+        Some(device) if device.api_key == api_key => Ok(()),
+        _ => Err(DeviceError::DeviceNotFound),
+    }
+}
+
 fn is_valid_api_key(key: &str) -> bool {
     if key.trim().is_empty() {
         return false;
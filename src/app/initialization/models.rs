@@ -103,8 +103,8 @@ pub const RESPONSE: &str = r#"
         "redeem_interstitial_page": "https://www.kobo.com",
         "love_dashboard_page": "https://www.kobo.com/{region}/{language}/kobosuperpoints",
         "help_page": "http://www.kobo.com/help",
-        "image_url_template": "{host}/images/{ImageId}&width={Width}&height={Height}",
-        "image_url_quality_template": "{host}/images/{ImageId}&width={Width}&height={Height}",
+        "image_url_template": "{host}/images/{ImageId}/{Width}/{Height}/false/image.jpg",
+        "image_url_quality_template": "{host}/images/{ImageId}/{Width}/{Height}/{Quality}/{IsGreyscale}/image.jpg",
         "customer_care_live_chat": "https://v2.zopim.com/widget/livechat.html?key=Y6gwUmnu4OATxN3Tli4Av9bYN319BTdO",
         "audiobook_landing_page": "https://www.kobo.com/{region}/{language}/audiobooks",
         "userguide_host": "https://ereaderfiles.kobo.com",
@@ -0,0 +1,168 @@
+use super::models::{MetadataOverride, MetadataOverrideError, MetadataOverrideRow};
+use sqlx::SqlitePool;
+
+pub async fn add_series(pool: &SqlitePool, series_id: &str, title: &str, author: &str) -> () {
+    sqlx::query(
+        r"
+        INSERT INTO series (series_id, title, author)
+        VALUES ($1, $2, $3)
+        ON CONFLICT(title, author) DO NOTHING
+        ",
+    )
+    .bind(series_id)
+    .bind(title)
+    .bind(author)
+    .execute(pool)
+    .await
+    .expect("Failed to add series");
+}
+
+pub async fn get_series_id(pool: &SqlitePool, title: &str, author: &str) -> Option<String> {
+    sqlx::query_scalar(
+        r"
+        SELECT series_id
+        FROM series
+        WHERE title = $1 AND author = $2
+        ",
+    )
+    .bind(title)
+    .bind(author)
+    .fetch_optional(pool)
+    .await
+    .expect("Failed to get series")
+}
+
+pub async fn set_override(
+    pool: &SqlitePool,
+    api_key: &str,
+    book_id: &str,
+    metadata_override: &MetadataOverride,
+) -> () {
+    let series_title = metadata_override.series.as_ref().map(|s| s.title.as_str());
+    let series_number = metadata_override.series.as_ref().map(|s| s.number);
+
+    sqlx::query(
+        r"
+        INSERT INTO metadata_overrides
+            (api_key, book_id, title, subtitle, series_title, series_number, sort_author, description)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT(api_key, book_id) DO UPDATE SET
+            title = excluded.title,
+            subtitle = excluded.subtitle,
+            series_title = excluded.series_title,
+            series_number = excluded.series_number,
+            sort_author = excluded.sort_author,
+            description = excluded.description
+        ",
+    )
+    .bind(api_key)
+    .bind(book_id)
+    .bind(&metadata_override.title)
+    .bind(&metadata_override.subtitle)
+    .bind(series_title)
+    .bind(series_number)
+    .bind(&metadata_override.sort_author)
+    .bind(&metadata_override.description)
+    .execute(pool)
+    .await
+    .expect("Failed to set metadata override");
+}
+
+pub async fn get_override(
+    pool: &SqlitePool,
+    api_key: &str,
+    book_id: &str,
+) -> Result<MetadataOverride, MetadataOverrideError> {
+    let row: MetadataOverrideRow = sqlx::query_as(
+        r"
+        SELECT title, subtitle, series_title, series_number, sort_author, description
+        FROM metadata_overrides
+        WHERE api_key = $1 AND book_id = $2
+        ",
+    )
+    .bind(api_key)
+    .bind(book_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.into())
+}
+
+pub async fn delete_override(
+    pool: &SqlitePool,
+    api_key: &str,
+    book_id: &str,
+) -> Result<(), MetadataOverrideError> {
+    let result = sqlx::query(
+        r"
+        DELETE FROM metadata_overrides
+        WHERE api_key = $1 AND book_id = $2
+        ",
+    )
+    .bind(api_key)
+    .bind(book_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete metadata override");
+
+    if result.rows_affected() == 0 {
+        return Err(MetadataOverrideError::OverrideNotFound);
+    }
+
+    Ok(())
+}
+
+pub async fn add_pending_override(pool: &SqlitePool, book_id: &str, device_id: &str) -> () {
+    sqlx::query(
+        r"
+        INSERT OR IGNORE INTO pending_overrides (book_id, device_id)
+        VALUES ($1, $2)
+        ",
+    )
+    .bind(book_id)
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to add pending metadata override");
+}
+
+pub async fn get_pending_overrides(pool: &SqlitePool, device_id: &str) -> Vec<String> {
+    sqlx::query_scalar(
+        r"
+        SELECT book_id
+        FROM pending_overrides
+        WHERE device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .fetch_all(pool)
+    .await
+    .expect("Failed to get pending metadata overrides")
+}
+
+pub async fn delete_pending_override(pool: &SqlitePool, book_id: &str, device_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM pending_overrides
+        WHERE book_id = $1 AND device_id = $2
+        ",
+    )
+    .bind(book_id)
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete pending metadata override");
+}
+
+pub async fn delete_pending_overrides(pool: &SqlitePool, device_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM pending_overrides
+        WHERE device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete pending metadata overrides");
+}
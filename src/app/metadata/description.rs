@@ -0,0 +1,59 @@
+use ammonia::Builder;
+use pulldown_cmark::{Event, Parser, html};
+use regex::Regex;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::LazyLock,
+};
+
+// The subset of HTML that the synopsis view of Kobo devices renders correctly
+const ALLOWED_TAGS: [&str; 11] = [
+    "p",
+    "br",
+    "b",
+    "strong",
+    "i",
+    "em",
+    "u",
+    "ul",
+    "ol",
+    "li",
+    "blockquote",
+];
+
+static HTML_TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"</?[a-zA-Z][a-zA-Z0-9]*(\s[^<>]*)?/?>").expect("Failed to compile regex"));
+
+/// Sanitizes a book description, keeping only the HTML that Kobo devices support.
+/// Descriptions without HTML are treated as Markdown, which also covers plain text.
+pub fn sanitize_description(description: &str) -> Option<String> {
+    let html = if HTML_TAG.is_match(description) {
+        description.to_string()
+    } else {
+        markdown_to_html(description)
+    };
+
+    let sanitized = Builder::new()
+        .tags(HashSet::from(ALLOWED_TAGS))
+        .clean_content_tags(HashSet::from(["script", "style", "noscript", "template"]))
+        .generic_attributes(HashSet::new())
+        .tag_attributes(HashMap::new())
+        .link_rel(None)
+        .clean(&html)
+        .to_string();
+
+    let sanitized = sanitized.trim();
+    (!sanitized.is_empty()).then(|| sanitized.to_string())
+}
+
+fn markdown_to_html(markdown: &str) -> String {
+    // Line breaks in plain text descriptions are intentional, so they are kept instead of being joined
+    let parser = Parser::new(markdown).map(|event| match event {
+        Event::SoftBreak => Event::HardBreak,
+        event => event,
+    });
+
+    let mut html = String::new();
+    html::push_html(&mut html, parser);
+    html
+}
@@ -1,8 +1,12 @@
-use super::service;
+use super::{
+    models::{MetadataOverride, MetadataOverrideError},
+    service,
+};
 use crate::app::{AppState, authentication::AuthToken, error::KoboError};
 use axum::{
     Extension, Json,
     extract::{Path, State},
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
 };
 use axum_extra::extract::Host;
@@ -24,7 +28,7 @@ pub async fn metadata_handler(
         &state.prosa_client,
         &book_id,
         &server_url,
-        state.config.download_token.book_expiration,
+        &state.config,
         &token.api_key,
         &token.device_id,
     )
@@ -32,3 +36,47 @@ pub async fn metadata_handler(
 
     Ok(Json(vec![response]))
 }
+
+pub async fn get_override_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(book_id): Path<String>,
+) -> Result<impl IntoResponse, KoboError> {
+    let api_key = headers
+        .get("api-key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(MetadataOverrideError::MissingApiKey)?;
+
+    let metadata_override =
+        service::get_override(&state.pool, &state.prosa_client, &book_id, api_key).await?;
+    Ok(Json(metadata_override))
+}
+
+pub async fn set_override_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(book_id): Path<String>,
+    Json(body): Json<MetadataOverride>,
+) -> Result<impl IntoResponse, KoboError> {
+    let api_key = headers
+        .get("api-key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(MetadataOverrideError::MissingApiKey)?;
+
+    service::set_override(&state.pool, &state.prosa_client, &book_id, &body, api_key).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn delete_override_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(book_id): Path<String>,
+) -> Result<impl IntoResponse, KoboError> {
+    let api_key = headers
+        .get("api-key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(MetadataOverrideError::MissingApiKey)?;
+
+    service::delete_override(&state.pool, &state.prosa_client, &book_id, api_key).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,6 +1,8 @@
+mod data;
+mod description;
 mod handlers;
 mod models;
 pub mod routes;
 pub mod service;
 
-pub use models::{BookMetadata, DownloadUrl};
+pub use models::{BookMetadata, ContributorRole, DownloadUrl, format_series_number};
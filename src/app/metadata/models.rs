@@ -1,6 +1,103 @@
+use super::description::sanitize_description;
 use crate::{app::state::service::unix_millis_to_string, client::ProsaMetadata};
 use isolang::Language;
-use serde::Serialize;
+use log::{debug, warn};
+use oxilangtag::LanguageTag;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use strum_macros::{AsRefStr, EnumMessage, EnumProperty};
+
+type SqlxError = sqlx::Error;
+
+// ISO 639-2 bibliographic codes, which many EPUBs use, mapped to their ISO 639-3 equivalents
+const BIBLIOGRAPHIC_CODES: [(&str, &str); 20] = [
+    ("alb", "sqi"),
+    ("arm", "hye"),
+    ("baq", "eus"),
+    ("bur", "mya"),
+    ("chi", "zho"),
+    ("cze", "ces"),
+    ("dut", "nld"),
+    ("fre", "fra"),
+    ("geo", "kat"),
+    ("ger", "deu"),
+    ("gre", "ell"),
+    ("ice", "isl"),
+    ("mac", "mkd"),
+    ("mao", "mri"),
+    ("may", "msa"),
+    ("per", "fas"),
+    ("rum", "ron"),
+    ("slo", "slk"),
+    ("tib", "bod"),
+    ("wel", "cym"),
+];
+
+#[derive(EnumMessage, EnumProperty, Debug)]
+pub enum MetadataOverrideError {
+    #[strum(message = "OverrideNotFound")]
+    #[strum(detailed_message = "No metadata override exists for this book.")]
+    #[strum(props(StatusCode = "404"))]
+    OverrideNotFound,
+    #[strum(message = "MissingApiKey")]
+    #[strum(detailed_message = "The api key must be provided.")]
+    #[strum(props(StatusCode = "400"))]
+    MissingApiKey,
+    #[strum(message = "Internal error")]
+    #[strum(props(StatusCode = "500"))]
+    InternalError,
+}
+
+impl From<SqlxError> for MetadataOverrideError {
+    fn from(error: SqlxError) -> Self {
+        match error {
+            SqlxError::RowNotFound => MetadataOverrideError::OverrideNotFound,
+            _ => MetadataOverrideError::InternalError,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct MetadataOverride {
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
+    pub series: Option<SeriesOverride>,
+    pub sort_author: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SeriesOverride {
+    pub title: String,
+    pub number: f32,
+}
+
+#[derive(FromRow)]
+pub struct MetadataOverrideRow {
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
+    pub series_title: Option<String>,
+    pub series_number: Option<f32>,
+    pub sort_author: Option<String>,
+    pub description: Option<String>,
+}
+
+impl From<MetadataOverrideRow> for MetadataOverride {
+    fn from(row: MetadataOverrideRow) -> Self {
+        let series = match (row.series_title, row.series_number) {
+            (Some(title), Some(number)) => Some(SeriesOverride { title, number }),
+            _ => None,
+        };
+
+        MetadataOverride {
+            title: row.title,
+            subtitle: row.subtitle,
+            series,
+            sort_author: row.sort_author,
+            description: row.description,
+        }
+    }
+}
 
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "PascalCase")]
@@ -52,6 +149,31 @@ pub struct Contributor {
     role: String,
 }
 
+#[derive(AsRefStr, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ContributorRole {
+    Author,
+    Illustrator,
+    Translator,
+    Editor,
+    Narrator,
+}
+
+impl ContributorRole {
+    /// Maps a Prosa role, either by name or by MARC relator code, onto Kobo's role vocabulary.
+    pub fn from_prosa(role: &str) -> Option<Self> {
+        match role.trim().to_lowercase().as_str() {
+            "author" | "aut" | "creator" | "cre" | "writer" | "wri" => Some(ContributorRole::Author),
+            "illustrator" | "ill" | "artist" | "art" | "cover artist" | "cov" => {
+                Some(ContributorRole::Illustrator)
+            }
+            "translator" | "trl" => Some(ContributorRole::Translator),
+            "editor" | "edt" => Some(ContributorRole::Editor),
+            "narrator" | "nrt" | "reader" => Some(ContributorRole::Narrator),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct DownloadUrl {
@@ -96,7 +218,7 @@ pub struct CurrentLoveDisplayPrice {
     pub total_amount: i64,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Default, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct Locale {
     pub language_code: String,
@@ -104,30 +226,216 @@ pub struct Locale {
     pub country_code: String,
 }
 
+impl Locale {
+    /// Parses a BCP-47 language tag, such as `en`, `pt_BR` or `zh-Hant-TW`, or an English language name.
+    pub fn parse(language: &str) -> Option<Self> {
+        let language = language.trim().replace('_', "-");
+
+        if let Some(language) = Language::from_name(&language) {
+            return Some(Locale::new(language, String::new(), String::new()));
+        }
+
+        let tag = LanguageTag::parse(language.as_str()).ok()?;
+        let primary_language = tag.primary_language().to_ascii_lowercase();
+
+        let language = match primary_language.len() {
+            2 => Language::from_639_1(&primary_language),
+            3 => {
+                let code = BIBLIOGRAPHIC_CODES
+                    .iter()
+                    .find(|(bibliographic, _)| *bibliographic == primary_language)
+                    .map_or(primary_language.as_str(), |(_, terminology)| terminology);
+                Language::from_639_3(code)
+            }
+            _ => None,
+        }?;
+
+        let script_code = tag.script().map(title_case).unwrap_or_default();
+        let country_code = tag.region().map(str::to_ascii_uppercase).unwrap_or_default();
+
+        Some(Locale::new(language, script_code, country_code))
+    }
+
+    fn new(language: Language, script_code: String, country_code: String) -> Self {
+        // Kobo devices expect two letter codes, falling back to three letters for languages that have none
+        let language_code = language.to_639_1().unwrap_or_else(|| language.to_639_3());
+
+        Locale {
+            language_code: language_code.to_string(),
+            script_code,
+            country_code,
+        }
+    }
+}
+
+/// Formats a series number without a trailing fraction for whole numbers, e.g. `2` or `1.5`.
+pub fn format_series_number(number: f32) -> String {
+    let number = format!("{number:.2}");
+    number.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+// Prosa stores the book's main identifier as its ISBN, even when it is some other identifier such as a URL.
+// External IDs are reported as `{scheme}:{value}`, so a valid ISBN becomes `isbn:{ISBN-13}`.
+fn translate_identifier(book_id: &str, identifier: &str) -> (Option<String>, Vec<String>) {
+    let identifier = identifier.trim();
+    let identifier = strip_prefix_ignore_case(identifier, "urn:").unwrap_or(identifier);
+
+    let isbn = match strip_prefix_ignore_case(identifier, "isbn:") {
+        Some(isbn) => Some(isbn),
+        None if identifier
+            .chars()
+            .all(|c| c.is_ascii_digit() || "xX- ".contains(c)) =>
+        {
+            Some(identifier)
+        }
+        None => None,
+    };
+
+    match isbn.map(|isbn| (isbn, normalize_isbn(isbn))) {
+        Some((_, Some(isbn))) => (Some(isbn.clone()), vec![format!("isbn:{isbn}")]),
+        Some((isbn, None)) => {
+            warn!("Ignoring invalid ISBN {isbn:?} of book {book_id}");
+            (None, Vec::new())
+        }
+        None if identifier.contains(':') => (None, vec![identifier.to_string()]),
+        None => {
+            debug!("Ignoring unrecognized identifier {identifier:?} of book {book_id}");
+            (None, Vec::new())
+        }
+    }
+}
+
+/// Validates an ISBN-10 or ISBN-13 and normalizes it to its ISBN-13 form, without separators.
+fn normalize_isbn(isbn: &str) -> Option<String> {
+    let isbn: String = isbn
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' '))
+        .collect::<String>()
+        .to_ascii_uppercase();
+
+    match isbn.len() {
+        10 => {
+            let mut sum = 0;
+            for (i, c) in isbn.chars().enumerate() {
+                let digit = match c {
+                    'X' if i == 9 => 10,
+                    c => c.to_digit(10)?,
+                };
+                sum += (10 - i as u32) * digit;
+            }
+
+            if sum % 11 != 0 {
+                return None;
+            }
+
+            let isbn = format!("978{}", &isbn[..9]);
+            let check_digit = (10 - isbn13_sum(&isbn)? % 10) % 10;
+            Some(format!("{isbn}{check_digit}"))
+        }
+        13 if isbn.starts_with("978") || isbn.starts_with("979") => {
+            (isbn13_sum(&isbn)? % 10 == 0).then_some(isbn)
+        }
+        _ => None,
+    }
+}
+
+fn isbn13_sum(digits: &str) -> Option<u32> {
+    let mut sum = 0;
+    for (i, c) in digits.chars().enumerate() {
+        let weight = if i % 2 == 0 { 1 } else { 3 };
+        sum += weight * c.to_digit(10)?;
+    }
+
+    Some(sum)
+}
+
+fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = text.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix).then(|| &text[prefix.len()..])
+}
+
+fn title_case(text: &str) -> String {
+    let text = text.to_ascii_lowercase();
+    let mut chars = text.chars();
+
+    match chars.next() {
+        Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
 impl BookMetadata {
+    /// Applies a user's override on top of the metadata translated from Prosa.
+    /// Series IDs are assigned afterwards, so an overridden series gets its own ID.
+    pub fn apply_override(&mut self, metadata_override: MetadataOverride) {
+        if let Some(title) = metadata_override.title {
+            self.title = Some(title);
+        }
+
+        if let Some(subtitle) = metadata_override.subtitle {
+            self.subtitle = Some(subtitle);
+        }
+
+        if let Some(description) = metadata_override.description {
+            self.description = sanitize_description(&description);
+        }
+
+        if let Some(series) = metadata_override.series {
+            self.series = Some(Series {
+                id: String::new(),
+                name: series.title,
+                number: format_series_number(series.number),
+                number_float: series.number,
+            });
+        }
+
+        // Devices display and sort books by their contributors, so the sort author replaces the authors
+        if let Some(sort_author) = metadata_override.sort_author {
+            self.contributors = vec![sort_author];
+        }
+    }
+
     pub fn new(book_id: &str, metadata: ProsaMetadata) -> Self {
         let publisher = Publisher {
             name: metadata.publisher.clone(),
             imprint: metadata.publisher,
         };
 
-        let contributors: Vec<Contributor> = metadata
+        let contributors: Vec<(Contributor, ContributorRole)> = metadata
             .contributors
             .unwrap_or_default()
             .into_iter()
-            .map(|c| Contributor {
-                name: c.name,
-                role: c.role,
+            .filter_map(|c| {
+                let Some(role) = ContributorRole::from_prosa(&c.role) else {
+                    debug!(
+                        "Ignoring contributor {:?} of book {book_id} with unknown role {:?}",
+                        c.name, c.role
+                    );
+                    return None;
+                };
+
+                let contributor = Contributor {
+                    name: c.name,
+                    role: role.as_ref().to_string(),
+                };
+
+                Some((contributor, role))
             })
             .collect();
 
+        // The series ID is only assigned once the series is registered, see `service::translate_series`
         let series = metadata.series.map(|s| Series {
-            id: s.title.clone(),
+            id: String::new(),
             name: s.title,
-            number: s.number.to_string(),
+            number: format_series_number(s.number),
             number_float: s.number,
         });
 
+        let (isbn, external_ids) = match metadata.isbn {
+            Some(identifier) => translate_identifier(book_id, &identifier),
+            None => (None, Vec::new()),
+        };
+
         let current_display_price = CurrentDisplayPrice {
             total_amount: -1,
             currency_code: String::new(),
@@ -135,16 +443,16 @@ impl BookMetadata {
 
         let current_love_display_price = CurrentLoveDisplayPrice { total_amount: 0 };
 
-        let locale = Locale {
-            language_code: String::new(),
-            script_code: String::new(),
-            country_code: String::new(),
+        let locale = match metadata.language.as_deref().map(|l| (l, Locale::parse(l))) {
+            Some((_, Some(locale))) => locale,
+            Some((language, None)) => {
+                debug!("Ignoring unrecognized language {language:?} of book {book_id}");
+                Locale::default()
+            }
+            None => Locale::default(),
         };
 
-        let language = match metadata.language {
-            Some(l) if l.len() > 3 => Language::from_name(&l).map(|l| l.to_639_3().to_string()),
-            l => l,
-        };
+        let language = Some(locale.language_code.clone()).filter(|l| !l.is_empty());
 
         BookMetadata {
             cross_revision_id: book_id.to_string(),
@@ -152,7 +460,7 @@ impl BookMetadata {
             publisher,
             publication_date: metadata.publication_date.map(unix_millis_to_string),
             language,
-            isbn: metadata.isbn,
+            isbn,
             subtitle: metadata.subtitle,
             genre: None,
             slug: None,
@@ -161,18 +469,22 @@ impl BookMetadata {
             cover_image_id: book_id.to_string(),
             is_social_enabled: true,
             work_id: book_id.to_string(),
-            external_ids: Vec::new(),
+            external_ids,
             is_pre_order: false,
-            contributor_roles: contributors.clone(),
+            contributor_roles: contributors.iter().map(|(c, _)| c.clone()).collect(),
             is_internet_archive: false,
             is_annotation_export_disabled: false,
             is_ai_summary_disabled: false,
             entitlement_id: book_id.to_string(),
             title: metadata.title,
-            description: metadata.description,
+            description: metadata.description.as_deref().and_then(sanitize_description),
             categories: Vec::new(),
             download_urls: vec![],
-            contributors: contributors.iter().map(|c| c.name.clone()).collect(),
+            contributors: contributors
+                .iter()
+                .filter(|(_, role)| *role == ContributorRole::Author)
+                .map(|(c, _)| c.name.clone())
+                .collect(),
             series,
             current_display_price,
             current_love_display_price,
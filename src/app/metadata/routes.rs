@@ -7,5 +7,9 @@ pub fn get_routes(state: AppState) -> Router {
     Router::new()
         .route("/v1/library/{book_id}/metadata", get(handlers::metadata_handler))
         .layer(from_fn_with_state(state.clone(), extract_token_middleware))
+        .route("/overrides/{book_id}", get(handlers::get_override_handler)
+            .put(handlers::set_override_handler)
+            .delete(handlers::delete_override_handler)
+        )
         .with_state(state)
 }
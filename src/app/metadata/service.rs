@@ -1,11 +1,16 @@
-use super::{BookMetadata, DownloadUrl};
+use super::{
+    BookMetadata, DownloadUrl, data,
+    models::{MetadataOverride, MetadataOverrideError},
+};
 use crate::{
-    app::{books, covers, error::KoboError},
+    app::{books, categories, covers, devices, error::KoboError},
     client::{
         ProsaMetadata,
         prosa::{Client, ClientError},
     },
+    config::Configuration,
 };
+use rand::RngCore;
 use sqlx::SqlitePool;
 
 pub async fn translate_metadata(
@@ -13,28 +18,201 @@ pub async fn translate_metadata(
     client: &Client,
     book_id: &str,
     server_url: &str,
-    book_expiration: i64,
+    config: &Configuration,
     api_key: &str,
     device_id: &str,
 ) -> Result<BookMetadata, KoboError> {
     let size_response = client.fetch_book_file_metadata(book_id, api_key)?.file_size;
-    let metadata_response = match client.fetch_metadata(book_id, api_key) {
-        Ok(response) => response,
-        Err(ClientError::NotFound) => ProsaMetadata::default(),
-        Err(e) => return Err(e.into()),
-    };
+    let (mut metadata, genres) = translate_prosa_metadata(pool, client, book_id, api_key).await?;
 
-    let mut metadata = BookMetadata::new(book_id, metadata_response);
+    let categories =
+        categories::translate_genres(pool, &config.categories, book_id, &genres, device_id).await;
+    metadata.genre = categories.first().map(|c| c.id.clone());
+    metadata.categories = categories.into_iter().map(|c| c.id).collect();
 
+    let book_expiration = config.download_token.book_expiration;
     let book_token = books::generate_token(pool, book_id, book_expiration, device_id).await;
     let download_url = format!("{server_url}/books/{book_id}?token={book_token}");
     let download_url = DownloadUrl::new(&download_url, size_response);
 
-    let cover_token = covers::get_token(pool, book_id, device_id).await;
-    let cover_token = format!("?token={cover_token}");
+    let cover_expiration = config.download_token.cover_expiration;
+    let cover_token = covers::get_token(pool, book_id, cover_expiration, device_id).await;
+    let cover_token = format!("/{cover_token}");
 
     metadata.download_urls.push(download_url);
     metadata.cover_image_id.push_str(&cover_token);
 
     Ok(metadata)
 }
+
+/// Translates the metadata of a book without issuing any download tokens for it, for callers
+/// that only need its series and contributors.
+pub async fn translate_book_metadata(
+    pool: &SqlitePool,
+    client: &Client,
+    book_id: &str,
+    api_key: &str,
+) -> Result<BookMetadata, KoboError> {
+    let (metadata, _) = translate_prosa_metadata(pool, client, book_id, api_key).await?;
+    Ok(metadata)
+}
+
+async fn translate_prosa_metadata(
+    pool: &SqlitePool,
+    client: &Client,
+    book_id: &str,
+    api_key: &str,
+) -> Result<(BookMetadata, Vec<String>), KoboError> {
+    let metadata_response = match client.fetch_metadata(book_id, api_key) {
+        Ok(response) => response,
+        Err(ClientError::NotFound) => ProsaMetadata::default(),
+        Err(e) => return Err(e.into()),
+    };
+
+    let genres = metadata_response.genres.clone().unwrap_or_default();
+    let mut metadata = BookMetadata::new(book_id, metadata_response);
+
+    // Series are keyed by the author from Prosa, even when the user overrides the sort author
+    let author = metadata.contributors.first().cloned().unwrap_or_default();
+
+    match data::get_override(pool, api_key, book_id).await {
+        Ok(metadata_override) => metadata.apply_override(metadata_override),
+        Err(MetadataOverrideError::OverrideNotFound) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    if let Some(series) = metadata.series.as_mut() {
+        series.id = translate_series(pool, &series.name, &author).await;
+    }
+
+    Ok((metadata, genres))
+}
+
+pub async fn get_override(
+    pool: &SqlitePool,
+    client: &Client,
+    book_id: &str,
+    api_key: &str,
+) -> Result<MetadataOverride, KoboError> {
+    // Makes sure the book exists and is accessible with the provided api key
+    client.fetch_book_file_metadata(book_id, api_key)?;
+
+    let metadata_override = data::get_override(pool, api_key, book_id).await?;
+    Ok(metadata_override)
+}
+
+pub async fn set_override(
+    pool: &SqlitePool,
+    client: &Client,
+    book_id: &str,
+    metadata_override: &MetadataOverride,
+    api_key: &str,
+) -> Result<(), KoboError> {
+    // Makes sure the book exists and is accessible with the provided api key
+    client.fetch_book_file_metadata(book_id, api_key)?;
+
+    data::set_override(pool, api_key, book_id, metadata_override).await;
+    add_pending_override(pool, book_id, api_key).await?;
+
+    Ok(())
+}
+
+pub async fn delete_override(
+    pool: &SqlitePool,
+    client: &Client,
+    book_id: &str,
+    api_key: &str,
+) -> Result<(), KoboError> {
+    // Makes sure the book exists and is accessible with the provided api key
+    client.fetch_book_file_metadata(book_id, api_key)?;
+
+    data::delete_override(pool, api_key, book_id).await?;
+    add_pending_override(pool, book_id, api_key).await?;
+
+    Ok(())
+}
+
+/// Returns the books whose overrides changed since the device's last sync and are still accessible.
+/// They stay pending until the sync sending them succeeds, see [`clear_pending_overrides`].
+pub async fn get_pending_overrides(
+    pool: &SqlitePool,
+    client: &Client,
+    api_key: &str,
+    device_id: &str,
+) -> Result<Vec<String>, KoboError> {
+    let mut books = Vec::new();
+
+    for book_id in data::get_pending_overrides(pool, device_id).await {
+        match client.fetch_book_file_metadata(&book_id, api_key) {
+            Ok(_) => books.push(book_id),
+            Err(ClientError::NotFound | ClientError::Forbidden) => {
+                data::delete_pending_override(pool, &book_id, device_id).await;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(books)
+}
+
+pub async fn clear_pending_overrides(pool: &SqlitePool, books: &[String], device_id: &str) {
+    for book_id in books {
+        data::delete_pending_override(pool, book_id, device_id).await;
+    }
+}
+
+pub async fn delete_pending_overrides(pool: &SqlitePool, device_id: &str) {
+    data::delete_pending_overrides(pool, device_id).await;
+}
+
+// Every device linked to the api key re-emits the book on its next sync
+async fn add_pending_override(pool: &SqlitePool, book_id: &str, api_key: &str) -> Result<(), KoboError> {
+    let linked_devices = devices::service::get_linked_devices(pool, api_key).await?;
+    for device_id in linked_devices {
+        data::add_pending_override(pool, book_id, &device_id).await;
+    }
+
+    Ok(())
+}
+
+// Series are identified by their title and first author, so that series sharing a name do not get grouped
+// together on the device. IDs are generated once and persisted, so they stay stable across syncs.
+async fn translate_series(pool: &SqlitePool, title: &str, author: &str) -> String {
+    let title = normalize_series_key(title);
+    let author = normalize_series_key(author);
+
+    if let Some(series_id) = data::get_series_id(pool, &title, &author).await {
+        return series_id;
+    }
+
+    let mut bytes = [0u8; 16];
+    rand::rng().fill_bytes(&mut bytes);
+
+    // Kobo devices expect series IDs to be UUIDs, so the random bytes are tagged as a version 4 UUID
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex = format!("{:032x}", u128::from_be_bytes(bytes));
+    let series_id = format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    );
+
+    data::add_series(pool, &series_id, &title, &author).await;
+
+    // Another request may have registered the same series concurrently, in which case its ID wins
+    data::get_series_id(pool, &title, &author)
+        .await
+        .expect("Failed to get registered series")
+}
+
+fn normalize_series_key(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
@@ -1,6 +1,7 @@
 mod annotations;
 mod authentication;
 mod books;
+mod categories;
 mod covers;
 mod devices;
 mod error;
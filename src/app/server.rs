@@ -1,5 +1,6 @@
 use super::{
-    annotations, authentication, books, covers, devices, initialization, metadata, proxy, state, sync,
+    annotations, authentication, books, categories, covers, devices, initialization, metadata, proxy, state,
+    sync,
 };
 use crate::{
     app::{shelves, tracing},
@@ -54,6 +55,7 @@ pub async fn run(config: Configuration, pool: SqlitePool) {
         .merge(state::routes::get_routes(state.clone()))
         .merge(annotations::routes::get_routes(state.clone()))
         .merge(shelves::routes::get_routes(state.clone()))
+        .merge(categories::routes::get_routes(state.clone()))
         .merge(proxy::routes::get_routes(state.clone()))
         .layer(from_fn(tracing::log_layer));
 
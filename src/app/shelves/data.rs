@@ -0,0 +1,543 @@
+use super::models::{SmartShelfBook, SyncedShelf, SyncedSmartShelf};
+use sqlx::SqlitePool;
+
+pub async fn set_device_shelf(
+    pool: &SqlitePool,
+    device_id: &str,
+    shelf_id: &str,
+    name: &str,
+    books: &str,
+) -> () {
+    sqlx::query(
+        r"
+        INSERT INTO device_shelves (device_id, shelf_id, name, books)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT(device_id, shelf_id) DO UPDATE SET
+            name = excluded.name,
+            books = excluded.books
+        ",
+    )
+    .bind(device_id)
+    .bind(shelf_id)
+    .bind(name)
+    .bind(books)
+    .execute(pool)
+    .await
+    .expect("Failed to set device shelf");
+}
+
+pub async fn get_device_shelf(pool: &SqlitePool, device_id: &str, shelf_id: &str) -> Option<SyncedShelf> {
+    sqlx::query_as(
+        r"
+        SELECT name, books
+        FROM device_shelves
+        WHERE device_id = $1 AND shelf_id = $2
+        ",
+    )
+    .bind(device_id)
+    .bind(shelf_id)
+    .fetch_optional(pool)
+    .await
+    .expect("Failed to get device shelf")
+}
+
+pub async fn delete_device_shelf(pool: &SqlitePool, device_id: &str, shelf_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM device_shelves
+        WHERE device_id = $1 AND shelf_id = $2
+        ",
+    )
+    .bind(device_id)
+    .bind(shelf_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete device shelf");
+}
+
+pub async fn add_shelf_order(pool: &SqlitePool, shelf_id: &str, book_id: &str) -> () {
+    sqlx::query(
+        r"
+        INSERT OR IGNORE INTO shelf_order (shelf_id, book_id, position)
+        SELECT $1, $2, COALESCE(MAX(position) + 1, 0)
+        FROM shelf_order
+        WHERE shelf_id = $1
+        ",
+    )
+    .bind(shelf_id)
+    .bind(book_id)
+    .execute(pool)
+    .await
+    .expect("Failed to add shelf order");
+}
+
+pub async fn get_shelf_order(pool: &SqlitePool, shelf_id: &str) -> Vec<String> {
+    sqlx::query_scalar(
+        r"
+        SELECT book_id
+        FROM shelf_order
+        WHERE shelf_id = $1
+        ORDER BY position
+        ",
+    )
+    .bind(shelf_id)
+    .fetch_all(pool)
+    .await
+    .expect("Failed to get shelf order")
+}
+
+pub async fn delete_shelf_book_order(pool: &SqlitePool, shelf_id: &str, book_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM shelf_order
+        WHERE shelf_id = $1 AND book_id = $2
+        ",
+    )
+    .bind(shelf_id)
+    .bind(book_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete shelf book order");
+}
+
+pub async fn delete_shelf_order(pool: &SqlitePool, shelf_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM shelf_order
+        WHERE shelf_id = $1
+        ",
+    )
+    .bind(shelf_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete shelf order");
+}
+
+pub async fn delete_book_shelf_order(pool: &SqlitePool, book_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM shelf_order
+        WHERE book_id = $1
+        ",
+    )
+    .bind(book_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete book shelf order");
+}
+
+pub async fn add_shared_shelf(pool: &SqlitePool, device_id: &str, shelf_id: &str) -> () {
+    sqlx::query(
+        r"
+        INSERT OR IGNORE INTO shared_shelves (device_id, shelf_id)
+        VALUES ($1, $2)
+        ",
+    )
+    .bind(device_id)
+    .bind(shelf_id)
+    .execute(pool)
+    .await
+    .expect("Failed to add shared shelf");
+}
+
+pub async fn get_shared_shelves(pool: &SqlitePool, device_id: &str) -> Vec<String> {
+    sqlx::query_scalar(
+        r"
+        SELECT shelf_id
+        FROM shared_shelves
+        WHERE device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .fetch_all(pool)
+    .await
+    .expect("Failed to get shared shelves")
+}
+
+pub async fn is_shared_shelf(pool: &SqlitePool, device_id: &str, shelf_id: &str) -> bool {
+    sqlx::query_scalar(
+        r"
+        SELECT COUNT(*) > 0
+        FROM shared_shelves
+        WHERE device_id = $1 AND shelf_id = $2
+        ",
+    )
+    .bind(device_id)
+    .bind(shelf_id)
+    .fetch_one(pool)
+    .await
+    .expect("Failed to check shared shelf")
+}
+
+pub async fn delete_shared_shelf(pool: &SqlitePool, device_id: &str, shelf_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM shared_shelves
+        WHERE device_id = $1 AND shelf_id = $2
+        ",
+    )
+    .bind(device_id)
+    .bind(shelf_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete shared shelf");
+}
+
+// A book only gets a finish date when it is seen changing to finished, since Prosa doesn't record one
+pub async fn set_smart_shelf_book(pool: &SqlitePool, device_id: &str, book: &SmartShelfBook, now: i64) -> () {
+    sqlx::query(
+        r"
+        INSERT INTO smart_shelf_books (
+            device_id, book_id, status, finished, series_id, series, series_number, authors
+        )
+        VALUES ($1, $2, $3, NULL, $4, $5, $6, $7)
+        ON CONFLICT(device_id, book_id) DO UPDATE SET
+            finished = CASE
+                WHEN excluded.status != 'Finished' THEN NULL
+                WHEN smart_shelf_books.status != 'Finished' THEN $8
+                ELSE smart_shelf_books.finished
+            END,
+            status = excluded.status,
+            series_id = excluded.series_id,
+            series = excluded.series,
+            series_number = excluded.series_number,
+            authors = excluded.authors
+        ",
+    )
+    .bind(device_id)
+    .bind(&book.book_id)
+    .bind(&book.status)
+    .bind(&book.series_id)
+    .bind(&book.series)
+    .bind(book.series_number)
+    .bind(&book.authors)
+    .bind(now)
+    .execute(pool)
+    .await
+    .expect("Failed to set smart shelf book");
+}
+
+// Books that were already recorded while syncing keep what was observed of them
+pub async fn add_smart_shelf_book(pool: &SqlitePool, device_id: &str, book: &SmartShelfBook) -> () {
+    sqlx::query(
+        r"
+        INSERT INTO smart_shelf_books (
+            device_id, book_id, status, finished, series_id, series, series_number, authors
+        )
+        VALUES ($1, $2, $3, NULL, $4, $5, $6, $7)
+        ON CONFLICT(device_id, book_id) DO NOTHING
+        ",
+    )
+    .bind(device_id)
+    .bind(&book.book_id)
+    .bind(&book.status)
+    .bind(&book.series_id)
+    .bind(&book.series)
+    .bind(book.series_number)
+    .bind(&book.authors)
+    .execute(pool)
+    .await
+    .expect("Failed to add smart shelf book");
+}
+
+pub async fn is_smart_shelf_seeded(pool: &SqlitePool, device_id: &str) -> bool {
+    sqlx::query_scalar(
+        r"
+        SELECT EXISTS(
+            SELECT 1
+            FROM seeded_smart_shelves
+            WHERE device_id = $1 AND complete
+        )
+        ",
+    )
+    .bind(device_id)
+    .fetch_one(pool)
+    .await
+    .expect("Failed to check seeded smart shelves")
+}
+
+pub async fn get_smart_shelf_seed_page(pool: &SqlitePool, device_id: &str) -> Option<i64> {
+    sqlx::query_scalar(
+        r"
+        SELECT next_page
+        FROM seeded_smart_shelves
+        WHERE device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .fetch_optional(pool)
+    .await
+    .expect("Failed to get smart shelf seed page")
+    .flatten()
+}
+
+pub async fn set_smart_shelf_seed_page(pool: &SqlitePool, device_id: &str, next_page: i64) -> () {
+    sqlx::query(
+        r"
+        INSERT INTO seeded_smart_shelves (device_id, next_page)
+        VALUES ($1, $2)
+        ON CONFLICT(device_id) DO UPDATE SET next_page = excluded.next_page
+        ",
+    )
+    .bind(device_id)
+    .bind(next_page)
+    .execute(pool)
+    .await
+    .expect("Failed to set smart shelf seed page");
+}
+
+pub async fn set_smart_shelf_seeded(pool: &SqlitePool, device_id: &str) -> () {
+    sqlx::query(
+        r"
+        INSERT INTO seeded_smart_shelves (device_id, complete)
+        VALUES ($1, 1)
+        ON CONFLICT(device_id) DO UPDATE SET complete = 1
+        ",
+    )
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to set seeded smart shelves");
+}
+
+pub async fn set_smart_shelf_book_status(
+    pool: &SqlitePool,
+    device_id: &str,
+    book_id: &str,
+    status: &str,
+    now: i64,
+) -> () {
+    sqlx::query(
+        r"
+        UPDATE smart_shelf_books
+        SET finished = CASE
+                WHEN $3 != 'Finished' THEN NULL
+                WHEN status != 'Finished' THEN $4
+                ELSE finished
+            END,
+            status = $3
+        WHERE device_id = $1 AND book_id = $2
+        ",
+    )
+    .bind(device_id)
+    .bind(book_id)
+    .bind(status)
+    .bind(now)
+    .execute(pool)
+    .await
+    .expect("Failed to set smart shelf book status");
+}
+
+pub async fn is_smart_shelf_book(pool: &SqlitePool, device_id: &str, book_id: &str) -> bool {
+    sqlx::query_scalar(
+        r"
+        SELECT COUNT(*) > 0
+        FROM smart_shelf_books
+        WHERE device_id = $1 AND book_id = $2
+        ",
+    )
+    .bind(device_id)
+    .bind(book_id)
+    .fetch_one(pool)
+    .await
+    .expect("Failed to check smart shelf book")
+}
+
+pub async fn get_smart_shelf_books(pool: &SqlitePool, device_id: &str) -> Vec<SmartShelfBook> {
+    sqlx::query_as(
+        r"
+        SELECT book_id, status, finished, series_id, series, series_number, authors
+        FROM smart_shelf_books
+        WHERE device_id = $1
+        ORDER BY book_id
+        ",
+    )
+    .bind(device_id)
+    .fetch_all(pool)
+    .await
+    .expect("Failed to get smart shelf books")
+}
+
+pub async fn get_series_books(pool: &SqlitePool, device_id: &str, series_id: &str) -> Vec<String> {
+    sqlx::query_scalar(
+        r"
+        SELECT book_id
+        FROM smart_shelf_books
+        WHERE device_id = $1 AND series_id = $2
+        ORDER BY series_number, book_id
+        ",
+    )
+    .bind(device_id)
+    .bind(series_id)
+    .fetch_all(pool)
+    .await
+    .expect("Failed to get series books")
+}
+
+pub async fn delete_smart_shelf_book(pool: &SqlitePool, device_id: &str, book_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM smart_shelf_books
+        WHERE device_id = $1 AND book_id = $2
+        ",
+    )
+    .bind(device_id)
+    .bind(book_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete smart shelf book");
+}
+
+pub async fn delete_book_smart_shelf_books(pool: &SqlitePool, book_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM smart_shelf_books
+        WHERE book_id = $1
+        ",
+    )
+    .bind(book_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete smart shelf books");
+}
+
+pub async fn set_smart_shelf(
+    pool: &SqlitePool,
+    device_id: &str,
+    shelf_id: &str,
+    name: &str,
+    books: &str,
+) -> () {
+    sqlx::query(
+        r"
+        INSERT INTO smart_shelves (device_id, shelf_id, name, books, resend)
+        VALUES ($1, $2, $3, $4, 0)
+        ON CONFLICT(device_id, shelf_id) DO UPDATE SET
+            name = excluded.name,
+            books = excluded.books,
+            resend = 0
+        ",
+    )
+    .bind(device_id)
+    .bind(shelf_id)
+    .bind(name)
+    .bind(books)
+    .execute(pool)
+    .await
+    .expect("Failed to set smart shelf");
+}
+
+pub async fn get_smart_shelves(pool: &SqlitePool, device_id: &str) -> Vec<SyncedSmartShelf> {
+    sqlx::query_as(
+        r"
+        SELECT shelf_id, name, books, resend
+        FROM smart_shelves
+        WHERE device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .fetch_all(pool)
+    .await
+    .expect("Failed to get smart shelves")
+}
+
+pub async fn is_smart_shelf(pool: &SqlitePool, device_id: &str, shelf_id: &str) -> bool {
+    sqlx::query_scalar(
+        r"
+        SELECT COUNT(*) > 0
+        FROM smart_shelves
+        WHERE device_id = $1 AND shelf_id = $2
+        ",
+    )
+    .bind(device_id)
+    .bind(shelf_id)
+    .fetch_one(pool)
+    .await
+    .expect("Failed to check smart shelf")
+}
+
+pub async fn resend_smart_shelf(pool: &SqlitePool, device_id: &str, shelf_id: &str) -> () {
+    sqlx::query(
+        r"
+        UPDATE smart_shelves
+        SET resend = 1
+        WHERE device_id = $1 AND shelf_id = $2
+        ",
+    )
+    .bind(device_id)
+    .bind(shelf_id)
+    .execute(pool)
+    .await
+    .expect("Failed to mark smart shelf for resend");
+}
+
+pub async fn delete_smart_shelf(pool: &SqlitePool, device_id: &str, shelf_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM smart_shelves
+        WHERE device_id = $1 AND shelf_id = $2
+        ",
+    )
+    .bind(device_id)
+    .bind(shelf_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete smart shelf");
+}
+
+pub async fn delete_device_shelves(pool: &SqlitePool, device_id: &str) -> () {
+    sqlx::query(
+        r"
+        DELETE FROM device_shelves
+        WHERE device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete device shelves");
+
+    sqlx::query(
+        r"
+        DELETE FROM shared_shelves
+        WHERE device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete device shared shelves");
+
+    sqlx::query(
+        r"
+        DELETE FROM smart_shelves
+        WHERE device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete device smart shelves");
+
+    sqlx::query(
+        r"
+        DELETE FROM smart_shelf_books
+        WHERE device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete device smart shelf books");
+
+    sqlx::query(
+        r"
+        DELETE FROM seeded_smart_shelves
+        WHERE device_id = $1
+        ",
+    )
+    .bind(device_id)
+    .execute(pool)
+    .await
+    .expect("Failed to delete device seeded smart shelves");
+}
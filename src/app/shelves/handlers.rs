@@ -21,17 +21,16 @@ pub async fn create_shelf_handler(
     Extension(token): Extension<AuthToken>,
     Json(request): Json<CreateShelfRequest>,
 ) -> Result<impl IntoResponse, KoboError> {
+    let books = service::resolve_shelf_items(&state.pool, request.items, &token.device_id).await;
     let shelf_id = service::translate_add_shelf(&state.prosa_client, &request.name, &token.api_key)?;
 
-    for book in request.items {
-        service::translate_add_book_to_shelf(
-            &state.prosa_client,
-            &shelf_id,
-            &book.revision_id,
-            &token.api_key,
-        )?;
+    for book_id in &books {
+        service::translate_add_book_to_shelf(&state.prosa_client, &shelf_id, book_id, &token.api_key)?;
     }
 
+    service::record_shelf_order(&state.pool, &shelf_id, &books, &[]).await;
+    service::track_new_shelf(&state.pool, &shelf_id, &request.name, &books, &token.device_id).await;
+
     Ok((StatusCode::CREATED, shelf_id))
 }
 
@@ -40,7 +39,12 @@ pub async fn delete_shelf_handler(
     Path(shelf_id): Path<String>,
     Extension(token): Extension<AuthToken>,
 ) -> Result<impl IntoResponse, KoboError> {
+    service::reject_smart_shelf_change(&state.pool, &shelf_id, &token.device_id).await?;
+    service::reject_shared_shelf_change(&state.pool, &shelf_id, &token.device_id).await?;
+
     service::translate_delete_shelf(&state.prosa_client, &shelf_id, &token.api_key)?;
+    service::untrack_shelf(&state.pool, &shelf_id, &token.device_id).await;
+    service::delete_shelf_order(&state.pool, &shelf_id).await;
 
     Ok(())
 }
@@ -51,7 +55,19 @@ pub async fn rename_shelf_handler(
     Extension(token): Extension<AuthToken>,
     Json(request): Json<RenameShelfRequest>,
 ) -> Result<impl IntoResponse, KoboError> {
+    service::reject_smart_shelf_change(&state.pool, &shelf_id, &token.device_id).await?;
+    service::reject_shared_shelf_change(&state.pool, &shelf_id, &token.device_id).await?;
+
     service::translate_rename_shelf(&state.prosa_client, &shelf_id, &request.name, &token.api_key)?;
+    service::track_shelf_change(
+        &state.pool,
+        &shelf_id,
+        Some(&request.name),
+        &[],
+        &[],
+        &token.device_id,
+    )
+    .await;
 
     Ok(())
 }
@@ -62,18 +78,19 @@ pub async fn add_book_to_shelf_handler(
     Extension(token): Extension<AuthToken>,
     Json(request): Json<AddBooksToShelfRequest>,
 ) -> Result<impl IntoResponse, KoboError> {
-    for book in &request.items {
-        service::translate_add_book_to_shelf(
-            &state.prosa_client,
-            &shelf_id,
-            &book.revision_id,
-            &token.api_key,
-        )?;
+    service::reject_smart_shelf_change(&state.pool, &shelf_id, &token.device_id).await?;
+    service::reject_shared_shelf_change(&state.pool, &shelf_id, &token.device_id).await?;
+
+    let books = service::resolve_shelf_items(&state.pool, request.items, &token.device_id).await;
+
+    for book_id in &books {
+        service::translate_add_book_to_shelf(&state.prosa_client, &shelf_id, book_id, &token.api_key)?;
     }
 
-    let response: Vec<String> = request.items.into_iter().map(|i| i.revision_id).collect();
+    service::record_shelf_order(&state.pool, &shelf_id, &books, &[]).await;
+    service::track_shelf_change(&state.pool, &shelf_id, None, &books, &[], &token.device_id).await;
 
-    Ok((StatusCode::CREATED, Json(response)))
+    Ok((StatusCode::CREATED, Json(books)))
 }
 
 pub async fn delete_books_from_shelf_handler(
@@ -82,14 +99,17 @@ pub async fn delete_books_from_shelf_handler(
     Extension(token): Extension<AuthToken>,
     Json(request): Json<DeleteBooksFromShelfRequest>,
 ) -> Result<impl IntoResponse, KoboError> {
-    for book in request.items {
-        service::translate_delete_book_from_shelf(
-            &state.prosa_client,
-            &shelf_id,
-            &book.revision_id,
-            &token.api_key,
-        )?;
+    service::reject_smart_shelf_change(&state.pool, &shelf_id, &token.device_id).await?;
+    service::reject_shared_shelf_change(&state.pool, &shelf_id, &token.device_id).await?;
+
+    let books = service::resolve_shelf_items(&state.pool, request.items, &token.device_id).await;
+
+    for book_id in &books {
+        service::translate_delete_book_from_shelf(&state.prosa_client, &shelf_id, book_id, &token.api_key)?;
     }
 
+    service::record_shelf_order(&state.pool, &shelf_id, &[], &books).await;
+    service::track_shelf_change(&state.pool, &shelf_id, None, &[], &books, &token.device_id).await;
+
     Ok(())
 }
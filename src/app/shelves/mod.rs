@@ -1,4 +1,18 @@
+mod data;
 mod handlers;
 pub mod models;
 pub mod routes;
 mod service;
+mod smart;
+
+pub use service::delete_book_shelves;
+pub use service::delete_device_shelves;
+pub use service::delete_device_smart_shelf_book;
+pub use service::delete_shelf_order;
+pub use service::order_shelf_books;
+pub use service::rebuild_smart_shelves;
+pub use service::record_smart_shelf_book;
+pub use service::refresh_smart_shelf_book_status;
+pub use service::save_sent_shelves;
+pub use service::sync_shared_shelves;
+pub use service::translate_shelf_update;
@@ -1,7 +1,21 @@
 use crate::app::state::service::unix_millis_to_string;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
+use sqlx::FromRow;
 use std::time::{SystemTime, UNIX_EPOCH};
+use strum_macros::{EnumMessage, EnumProperty};
+
+#[derive(EnumMessage, EnumProperty, Debug)]
+pub enum ShelfError {
+    #[strum(message = "ReadOnlyShelf")]
+    #[strum(detailed_message = "Smart shelves are generated by the server and cannot be modified.")]
+    #[strum(props(StatusCode = "403"))]
+    ReadOnlyShelf,
+    #[strum(message = "SharedShelf")]
+    #[strum(detailed_message = "Shared shelves can only be modified by their owner.")]
+    #[strum(props(StatusCode = "403"))]
+    SharedShelf,
+}
 
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "PascalCase")]
@@ -9,6 +23,12 @@ pub struct NewShelfResponse {
     pub new_tag: ShelfResponse,
 }
 
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct ChangedShelfResponse {
+    pub changed_tag: ShelfResponse,
+}
+
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct DeletedShelfResponse {
@@ -29,11 +49,15 @@ pub struct Tag {
     pub name: Option<String>,
     pub r#type: Option<String>,
     pub items: Option<Vec<ShelfItem>>,
+    pub deleted_items: Option<Vec<ShelfItem>>,
     pub created: Option<String>,
     pub last_modified: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+pub const BOOK_ITEM_TYPE: &str = "ProductRevisionTagItem";
+pub const SERIES_ITEM_TYPE: &str = "SeriesTagItem";
+
+#[derive(Serialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct ShelfItem {
     pub revision_id: String,
@@ -41,7 +65,7 @@ pub struct ShelfItem {
 }
 
 impl NewShelfResponse {
-    pub fn new(id: &str, name: &str, book_ids: &Vec<String>) -> Self {
+    pub fn new(id: &str, name: &str, book_ids: &[String]) -> Self {
         let now: i64 = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .expect("Failed to get time since epoch")
@@ -55,7 +79,7 @@ impl NewShelfResponse {
         for id in book_ids {
             let item = ShelfItem {
                 revision_id: id.to_string(),
-                r#type: "ProductRevisionTagItem".to_string(),
+                r#type: BOOK_ITEM_TYPE.to_string(),
             };
             items.push(item);
         }
@@ -65,6 +89,7 @@ impl NewShelfResponse {
             name: Some(name.to_string()),
             r#type: Some("UserTag".to_string()),
             items: Some(items),
+            deleted_items: None,
             created: Some(now.clone()),
             last_modified: now,
         };
@@ -75,6 +100,45 @@ impl NewShelfResponse {
     }
 }
 
+impl ChangedShelfResponse {
+    /// Only carries what changed since the device last received the shelf. The name is
+    /// omitted when it didn't change.
+    pub fn new(id: &str, name: Option<&str>, added: &[String], removed: &[String]) -> Self {
+        let now: i64 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Failed to get time since epoch")
+            .as_millis()
+            .try_into()
+            .expect("Failed to get current timestamp");
+
+        let now = unix_millis_to_string(now);
+
+        let to_items = |book_ids: &[String]| {
+            book_ids
+                .iter()
+                .map(|id| ShelfItem {
+                    revision_id: id.clone(),
+                    r#type: BOOK_ITEM_TYPE.to_string(),
+                })
+                .collect()
+        };
+
+        let tag = Tag {
+            id: id.to_string(),
+            name: name.map(ToString::to_string),
+            r#type: Some("UserTag".to_string()),
+            items: Some(to_items(added)),
+            deleted_items: Some(to_items(removed)),
+            created: None,
+            last_modified: now,
+        };
+
+        ChangedShelfResponse {
+            changed_tag: ShelfResponse { tag },
+        }
+    }
+}
+
 impl DeletedShelfResponse {
     pub fn new(id: &str) -> Self {
         let now: i64 = SystemTime::now()
@@ -91,6 +155,7 @@ impl DeletedShelfResponse {
             name: None,
             r#type: None,
             items: None,
+            deleted_items: None,
             created: None,
             last_modified: now,
         };
@@ -101,10 +166,75 @@ impl DeletedShelfResponse {
     }
 }
 
+/// What a device must receive to bring its copy of a shelf up to date.
+#[derive(Debug)]
+pub enum ShelfUpdate {
+    New(NewShelfResponse),
+    Changed(ChangedShelfResponse),
+}
+
+/// The shelves sent to a device during a sync. They are only saved once the whole sync response
+/// is built, so a sync that fails sends the same changes again when the device retries.
+#[derive(Default)]
+pub struct SentShelves {
+    pub changes: Vec<SentShelf>,
+}
+
+pub enum SentShelf {
+    Tracked {
+        shelf_id: String,
+        name: String,
+        books: Vec<String>,
+    },
+    Untracked(String),
+    Shared(String),
+    Unshared(String),
+    Smart {
+        shelf_id: String,
+        name: String,
+        books: Vec<String>,
+    },
+    SmartDeleted(String),
+}
+
+impl SentShelves {
+    pub fn track(&mut self, shelf_id: &str, name: &str, books: &[String]) {
+        self.changes.push(SentShelf::Tracked {
+            shelf_id: shelf_id.to_string(),
+            name: name.to_string(),
+            books: books.to_vec(),
+        });
+    }
+
+    pub fn untrack(&mut self, shelf_id: &str) {
+        self.changes.push(SentShelf::Untracked(shelf_id.to_string()));
+    }
+
+    pub fn share(&mut self, shelf_id: &str) {
+        self.changes.push(SentShelf::Shared(shelf_id.to_string()));
+    }
+
+    pub fn unshare(&mut self, shelf_id: &str) {
+        self.changes.push(SentShelf::Unshared(shelf_id.to_string()));
+    }
+
+    pub fn smart(&mut self, shelf_id: &str, name: &str, books: &[String]) {
+        self.changes.push(SentShelf::Smart {
+            shelf_id: shelf_id.to_string(),
+            name: name.to_string(),
+            books: books.to_vec(),
+        });
+    }
+
+    pub fn delete_smart(&mut self, shelf_id: &str) {
+        self.changes.push(SentShelf::SmartDeleted(shelf_id.to_string()));
+    }
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct CreateShelfRequest {
-    pub items: Vec<ShelfItem>,
+    pub items: Vec<ShelfItemRequest>,
     pub name: String,
 }
 
@@ -117,11 +247,75 @@ pub struct RenameShelfRequest {
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct AddBooksToShelfRequest {
-    pub items: Vec<ShelfItem>,
+    pub items: Vec<ShelfItemRequest>,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct DeleteBooksFromShelfRequest {
-    pub items: Vec<ShelfItem>,
+    pub items: Vec<ShelfItemRequest>,
+}
+
+/// A shelf item sent by the device. Items of types the middleware doesn't know are kept
+/// as `Unsupported`, so one of them doesn't fail the whole request.
+#[derive(Deserialize, Debug)]
+#[serde(from = "RawShelfItem")]
+pub enum ShelfItemRequest {
+    Book(String),
+    Series(String),
+    Unsupported(String),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct RawShelfItem {
+    #[serde(default)]
+    r#type: String,
+    revision_id: Option<String>,
+    series_id: Option<String>,
+}
+
+impl From<RawShelfItem> for ShelfItemRequest {
+    fn from(item: RawShelfItem) -> Self {
+        match (item.r#type.as_str(), item.revision_id, item.series_id) {
+            (BOOK_ITEM_TYPE, Some(revision_id), _) => ShelfItemRequest::Book(revision_id),
+            (SERIES_ITEM_TYPE, _, Some(series_id)) => ShelfItemRequest::Series(series_id),
+            _ => ShelfItemRequest::Unsupported(item.r#type),
+        }
+    }
+}
+
+/// A book as last synced to a device, with the state smart shelves are computed from.
+#[derive(FromRow, Debug)]
+pub struct SmartShelfBook {
+    pub book_id: String,
+    pub status: String,
+    pub finished: Option<i64>,
+    pub series_id: Option<String>,
+    pub series: Option<String>,
+    pub series_number: Option<f32>,
+    pub authors: String,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct SmartShelf {
+    pub id: String,
+    pub name: String,
+    pub books: Vec<String>,
+}
+
+/// A shelf as last sent to a device. Its books are stored as a JSON array.
+#[derive(FromRow, Debug)]
+pub struct SyncedShelf {
+    pub name: String,
+    pub books: String,
+}
+
+/// A smart shelf as last sent to a device. Its books are stored as a JSON array.
+#[derive(FromRow, Debug)]
+pub struct SyncedSmartShelf {
+    pub shelf_id: String,
+    pub name: String,
+    pub books: String,
+    pub resend: bool,
 }
@@ -1,6 +1,27 @@
+use super::{
+    data,
+    models::{
+        ChangedShelfResponse, NewShelfResponse, SentShelf, SentShelves, ShelfError, ShelfItemRequest,
+        ShelfUpdate, SmartShelfBook,
+    },
+    smart,
+};
 use crate::{
-    app::error::KoboError,
+    app::{
+        devices::{self, ShelfShare},
+        error::KoboError,
+        metadata::{self, BookMetadata},
+        state,
+    },
     client::prosa::{Client, ClientError},
+    config::SmartShelfRule,
+};
+use chrono::{Datelike, Utc};
+use log::warn;
+use sqlx::SqlitePool;
+use std::{
+    collections::HashSet,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 pub fn translate_add_shelf(client: &Client, shelf_name: &str, api_key: &str) -> Result<String, KoboError> {
@@ -51,3 +72,436 @@ pub fn translate_delete_book_from_shelf(
     }
     Ok(())
 }
+
+/// Turns the items sent by the device into book IDs, in the order they were sent.
+/// Series are expanded into their books on the device, and items of unsupported types are skipped.
+pub async fn resolve_shelf_items(
+    pool: &SqlitePool,
+    items: Vec<ShelfItemRequest>,
+    device_id: &str,
+) -> Vec<String> {
+    let mut books: Vec<String> = Vec::new();
+
+    for item in items {
+        let resolved = match item {
+            ShelfItemRequest::Book(book_id) => vec![book_id],
+            ShelfItemRequest::Series(series_id) => data::get_series_books(pool, device_id, &series_id).await,
+            ShelfItemRequest::Unsupported(item_type) => {
+                warn!("Ignoring shelf item of unsupported type {item_type:?}");
+                continue;
+            }
+        };
+
+        for book_id in resolved {
+            if !books.contains(&book_id) {
+                books.push(book_id);
+            }
+        }
+    }
+
+    books
+}
+
+/// Sorts the books of a shelf in the order they were added. Prosa doesn't keep an order,
+/// so books the middleware hasn't seen yet are placed last, in the order Prosa lists them.
+pub async fn order_shelf_books(pool: &SqlitePool, shelf_id: &str, books: Vec<String>) -> Vec<String> {
+    let mut order = data::get_shelf_order(pool, shelf_id).await;
+    let known: HashSet<&String> = order.iter().collect();
+
+    let unknown: Vec<String> = books
+        .iter()
+        .filter(|book| !known.contains(book))
+        .cloned()
+        .collect();
+    for book_id in &unknown {
+        data::add_shelf_order(pool, shelf_id, book_id).await;
+    }
+
+    let current: HashSet<&String> = books.iter().collect();
+    order.retain(|book| current.contains(book));
+    order.extend(unknown);
+
+    order
+}
+
+pub async fn record_shelf_order(pool: &SqlitePool, shelf_id: &str, added: &[String], removed: &[String]) {
+    for book_id in added {
+        data::add_shelf_order(pool, shelf_id, book_id).await;
+    }
+
+    for book_id in removed {
+        data::delete_shelf_book_order(pool, shelf_id, book_id).await;
+    }
+}
+
+pub async fn delete_shelf_order(pool: &SqlitePool, shelf_id: &str) {
+    data::delete_shelf_order(pool, shelf_id).await;
+}
+
+/// Compares a shelf with the copy the device last received, returning what it must be sent.
+/// The new copy is recorded in `sent`, to be saved once the sync response is complete.
+pub async fn translate_shelf_update(
+    pool: &SqlitePool,
+    shelf_id: &str,
+    name: &str,
+    books: &[String],
+    sent: &mut SentShelves,
+    device_id: &str,
+) -> Option<ShelfUpdate> {
+    let synced = data::get_device_shelf(pool, device_id, shelf_id).await;
+    let previous = synced.map(|shelf| (shelf.name, parse_books(&shelf.books)));
+
+    let update = diff_shelf(shelf_id, previous, name, books)?;
+    sent.track(shelf_id, name, books);
+
+    Some(update)
+}
+
+/// Saves the shelves a sync sent to the device, once its response is complete.
+pub async fn save_sent_shelves(pool: &SqlitePool, sent: SentShelves, device_id: &str) {
+    for change in sent.changes {
+        match change {
+            SentShelf::Tracked {
+                shelf_id,
+                name,
+                books,
+            } => {
+                data::set_device_shelf(pool, device_id, &shelf_id, &name, &serialize_books(&books)).await;
+            }
+            SentShelf::Untracked(shelf_id) => data::delete_device_shelf(pool, device_id, &shelf_id).await,
+            SentShelf::Shared(shelf_id) => data::add_shared_shelf(pool, device_id, &shelf_id).await,
+            SentShelf::Unshared(shelf_id) => {
+                data::delete_shared_shelf(pool, device_id, &shelf_id).await;
+                data::delete_device_shelf(pool, device_id, &shelf_id).await;
+            }
+            SentShelf::Smart {
+                shelf_id,
+                name,
+                books,
+            } => {
+                data::set_smart_shelf(pool, device_id, &shelf_id, &name, &serialize_books(&books)).await;
+            }
+            SentShelf::SmartDeleted(shelf_id) => data::delete_smart_shelf(pool, device_id, &shelf_id).await,
+        }
+    }
+}
+
+/// Records a shelf created by the device, so the next sync doesn't send it back.
+pub async fn track_new_shelf(
+    pool: &SqlitePool,
+    shelf_id: &str,
+    name: &str,
+    books: &[String],
+    device_id: &str,
+) {
+    data::set_device_shelf(pool, device_id, shelf_id, name, &serialize_books(books)).await;
+}
+
+/// Records a change the device made to one of its shelves, so the next sync doesn't send it back.
+pub async fn track_shelf_change(
+    pool: &SqlitePool,
+    shelf_id: &str,
+    name: Option<&str>,
+    added: &[String],
+    removed: &[String],
+    device_id: &str,
+) {
+    let Some(shelf) = data::get_device_shelf(pool, device_id, shelf_id).await else {
+        return;
+    };
+
+    let mut books = parse_books(&shelf.books);
+    books.retain(|book| !removed.contains(book));
+    for book in added {
+        if !books.contains(book) {
+            books.push(book.clone());
+        }
+    }
+
+    let name = name.unwrap_or(&shelf.name);
+    data::set_device_shelf(pool, device_id, shelf_id, name, &serialize_books(&books)).await;
+}
+
+pub async fn untrack_shelf(pool: &SqlitePool, shelf_id: &str, device_id: &str) {
+    data::delete_device_shelf(pool, device_id, shelf_id).await;
+}
+
+/// Rejects changes from the device to a smart shelf, and sends the shelf again on the next sync
+/// so the device restores its own copy.
+pub async fn reject_smart_shelf_change(
+    pool: &SqlitePool,
+    shelf_id: &str,
+    device_id: &str,
+) -> Result<(), ShelfError> {
+    if !data::is_smart_shelf(pool, device_id, shelf_id).await {
+        return Ok(());
+    }
+
+    data::resend_smart_shelf(pool, device_id, shelf_id).await;
+    Err(ShelfError::ReadOnlyShelf)
+}
+
+/// Rejects changes from the device to a shelf shared with it, and sends the shelf again on the next sync
+/// so the device restores its own copy.
+pub async fn reject_shared_shelf_change(
+    pool: &SqlitePool,
+    shelf_id: &str,
+    device_id: &str,
+) -> Result<(), ShelfError> {
+    if !data::is_shared_shelf(pool, device_id, shelf_id).await {
+        return Ok(());
+    }
+
+    data::delete_device_shelf(pool, device_id, shelf_id).await;
+    Err(ShelfError::SharedShelf)
+}
+
+/// Mirrors the shelves a device accepted, reading them with the API key of the device sharing them.
+/// Only the shelf's books the device's own API key can access are mirrored. Shelves that are no longer shared, or
+/// that their owner can't read anymore, are deleted from the device.
+pub async fn sync_shared_shelves(
+    pool: &SqlitePool,
+    client: &Client,
+    api_key: &str,
+    sent: &mut SentShelves,
+    device_id: &str,
+) -> Result<(Vec<ShelfUpdate>, Vec<String>), KoboError> {
+    let mut updates = Vec::new();
+    let mut available = HashSet::new();
+
+    // Shelves shared by another device of the same user are already synced as the user's own
+    let shared: Vec<ShelfShare> = devices::service::get_device_shelf_shares(pool, device_id)
+        .await
+        .into_iter()
+        .filter(|shelf| shelf.owner_api_key != api_key)
+        .collect();
+
+    for shelf in &shared {
+        let owner_key = &shelf.owner_api_key;
+        let contents = client
+            .get_shelf_metadata(&shelf.shelf_id, owner_key)
+            .and_then(|metadata| {
+                let books = client.list_books_in_shelf(&shelf.shelf_id, owner_key)?;
+                Ok((metadata.name, books))
+            });
+
+        let (name, books) = match contents {
+            Ok(contents) => contents,
+            Err(ClientError::NotFound | ClientError::Unauthorized | ClientError::Forbidden) => {
+                warn!(
+                    "Shared shelf {} can't be read with its owner's API key",
+                    shelf.shelf_id
+                );
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut accessible = Vec::new();
+        for book_id in books {
+            match client.fetch_book_file_metadata(&book_id, api_key) {
+                Ok(_) => accessible.push(book_id),
+                Err(ClientError::NotFound | ClientError::Forbidden) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        let books = order_shelf_books(pool, &shelf.shelf_id, accessible).await;
+
+        sent.share(&shelf.shelf_id);
+        available.insert(shelf.shelf_id.as_str());
+
+        if let Some(update) =
+            translate_shelf_update(pool, &shelf.shelf_id, &name, &books, sent, device_id).await
+        {
+            updates.push(update);
+        }
+    }
+
+    let mut deleted = Vec::new();
+    for shelf_id in data::get_shared_shelves(pool, device_id).await {
+        if !available.contains(shelf_id.as_str()) {
+            sent.unshare(&shelf_id);
+            deleted.push(shelf_id);
+        }
+    }
+
+    Ok((updates, deleted))
+}
+
+pub async fn record_smart_shelf_book(
+    pool: &SqlitePool,
+    book_id: &str,
+    status: &str,
+    metadata: &BookMetadata,
+    device_id: &str,
+) {
+    let book = smart_shelf_book(book_id, status, metadata);
+    data::set_smart_shelf_book(pool, device_id, &book, now()).await;
+}
+
+fn smart_shelf_book(book_id: &str, status: &str, metadata: &BookMetadata) -> SmartShelfBook {
+    SmartShelfBook {
+        book_id: book_id.to_string(),
+        status: status.to_string(),
+        finished: None,
+        series_id: metadata.series.as_ref().map(|series| series.id.clone()),
+        series: metadata.series.as_ref().map(|series| series.name.clone()),
+        series_number: metadata.series.as_ref().map(|series| series.number_float),
+        authors: serde_json::to_string(&metadata.contributors).expect("Failed to serialize authors"),
+    }
+}
+
+// Books are otherwise only recorded as they are synced, so a device linked to an existing library
+// would start with partial shelves. Prosa doesn't record when a book was finished, so books that
+// were already finished only count towards the finished shelf once they are finished again.
+// Each sync seeds a single page of the library, so a large library doesn't make a sync time out.
+async fn seed_smart_shelf_books(
+    pool: &SqlitePool,
+    client: &Client,
+    api_key: &str,
+    device_id: &str,
+) -> Result<(), KoboError> {
+    let page = data::get_smart_shelf_seed_page(pool, device_id).await;
+    let response = client.search_books(page.map(|page| page as u64), api_key)?;
+
+    if response.book_ids.is_empty() {
+        data::set_smart_shelf_seeded(pool, device_id).await;
+        return Ok(());
+    }
+
+    for book_id in response.book_ids {
+        let reading_state = state::service::translate_get_state(client, &book_id, api_key)?;
+        let metadata = metadata::service::translate_book_metadata(pool, client, &book_id, api_key).await?;
+
+        let book = smart_shelf_book(&book_id, &reading_state.status_info.status, &metadata);
+        data::add_smart_shelf_book(pool, device_id, &book).await;
+    }
+
+    data::set_smart_shelf_seed_page(pool, device_id, response.current_page as i64 + 1).await;
+
+    Ok(())
+}
+
+pub async fn refresh_smart_shelf_book_status(
+    pool: &SqlitePool,
+    client: &Client,
+    book_id: &str,
+    api_key: &str,
+    device_id: &str,
+) -> Result<(), KoboError> {
+    if !data::is_smart_shelf_book(pool, device_id, book_id).await {
+        return Ok(());
+    }
+
+    let reading_state = state::service::translate_get_state(client, book_id, api_key)?;
+    let status = reading_state.status_info.status;
+    data::set_smart_shelf_book_status(pool, device_id, book_id, &status, now()).await;
+
+    Ok(())
+}
+
+/// Rebuilds the smart shelves of a device, returning the updates it must receive and the IDs of the
+/// shelves it must delete. The first builds seed the books of the shelves from the library in Prosa.
+pub async fn rebuild_smart_shelves(
+    pool: &SqlitePool,
+    client: &Client,
+    rules: &[SmartShelfRule],
+    api_key: &str,
+    sent: &mut SentShelves,
+    device_id: &str,
+) -> Result<(Vec<ShelfUpdate>, Vec<String>), KoboError> {
+    if !rules.is_empty() && !data::is_smart_shelf_seeded(pool, device_id).await {
+        seed_smart_shelf_books(pool, client, api_key, device_id).await?;
+    }
+
+    let books = data::get_smart_shelf_books(pool, device_id).await;
+    let shelves = smart::build_smart_shelves(rules, &books, Utc::now().year());
+    let synced = data::get_smart_shelves(pool, device_id).await;
+
+    let mut deleted = Vec::new();
+    for shelf in &synced {
+        if shelves.iter().all(|s| s.id != shelf.shelf_id) {
+            sent.delete_smart(&shelf.shelf_id);
+            deleted.push(shelf.shelf_id.clone());
+        }
+    }
+
+    let mut updates = Vec::new();
+    for shelf in shelves {
+        let previous = synced
+            .iter()
+            .find(|s| s.shelf_id == shelf.id && !s.resend)
+            .map(|s| (s.name.clone(), parse_books(&s.books)));
+
+        if let Some(update) = diff_shelf(&shelf.id, previous, &shelf.name, &shelf.books) {
+            sent.smart(&shelf.id, &shelf.name, &shelf.books);
+            updates.push(update);
+        }
+    }
+
+    Ok((updates, deleted))
+}
+
+pub async fn delete_device_smart_shelf_book(pool: &SqlitePool, book_id: &str, device_id: &str) {
+    data::delete_smart_shelf_book(pool, device_id, book_id).await;
+}
+
+pub async fn delete_book_shelves(pool: &SqlitePool, book_id: &str) {
+    data::delete_book_smart_shelf_books(pool, book_id).await;
+    data::delete_book_shelf_order(pool, book_id).await;
+}
+
+pub async fn delete_device_shelves(pool: &SqlitePool, device_id: &str) {
+    data::delete_device_shelves(pool, device_id).await;
+}
+
+// Devices that never received a shelf get all of it, the others only the books that were added or removed
+fn diff_shelf(
+    shelf_id: &str,
+    previous: Option<(String, Vec<String>)>,
+    name: &str,
+    books: &[String],
+) -> Option<ShelfUpdate> {
+    let Some((previous_name, previous_books)) = previous else {
+        return Some(ShelfUpdate::New(NewShelfResponse::new(shelf_id, name, books)));
+    };
+
+    let current: HashSet<&String> = books.iter().collect();
+    let previous: HashSet<&String> = previous_books.iter().collect();
+
+    let added: Vec<String> = books
+        .iter()
+        .filter(|book| !previous.contains(book))
+        .cloned()
+        .collect();
+    let removed: Vec<String> = previous_books
+        .iter()
+        .filter(|book| !current.contains(book))
+        .cloned()
+        .collect();
+    let renamed = previous_name != name;
+
+    if added.is_empty() && removed.is_empty() && !renamed {
+        return None;
+    }
+
+    let name = renamed.then_some(name);
+    Some(ShelfUpdate::Changed(ChangedShelfResponse::new(
+        shelf_id, name, &added, &removed,
+    )))
+}
+
+fn parse_books(books: &str) -> Vec<String> {
+    serde_json::from_str(books).expect("Failed to deserialize shelf books")
+}
+
+fn serialize_books(books: &[String]) -> String {
+    serde_json::to_string(books).expect("Failed to serialize shelf books")
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs() as i64
+}
@@ -0,0 +1,101 @@
+use super::models::{SmartShelf, SmartShelfBook};
+use crate::config::SmartShelfRule;
+use chrono::{DateTime, Datelike};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+/// Computes the smart shelves of a device from the books it was sent, in the order of its rules.
+/// Status shelves are always present, while series and author shelves only exist while they have books.
+pub fn build_smart_shelves(rules: &[SmartShelfRule], books: &[SmartShelfBook], year: i32) -> Vec<SmartShelf> {
+    let mut shelves = Vec::new();
+
+    for rule in rules {
+        match rule {
+            SmartShelfRule::CurrentlyReading => {
+                let books = books.iter().filter(|book| book.status == "Reading");
+                shelves.push(smart_shelf("currently_reading", "Currently reading", books));
+            }
+            SmartShelfRule::Unread => {
+                let books = books.iter().filter(|book| book.status == "ReadyToRead");
+                shelves.push(smart_shelf("unread", "Unread", books));
+            }
+            SmartShelfRule::FinishedThisYear => {
+                let mut finished: Vec<(i64, &SmartShelfBook)> = books
+                    .iter()
+                    .filter(|book| book.status == "Finished")
+                    .filter_map(|book| book.finished.map(|finished| (finished, book)))
+                    .filter(|(finished, _)| {
+                        DateTime::from_timestamp(*finished, 0).is_some_and(|date| date.year() == year)
+                    })
+                    .collect();
+                finished.sort_by_key(|(finished, _)| *finished);
+
+                let name = format!("Finished in {year}");
+                shelves.push(smart_shelf(
+                    "finished_this_year",
+                    &name,
+                    finished.into_iter().map(|(_, book)| book),
+                ));
+            }
+            SmartShelfRule::Series => {
+                // Series sharing a name are told apart by their ID, but shelves are still ordered by name
+                let mut series: BTreeMap<(&str, &str), Vec<&SmartShelfBook>> = BTreeMap::new();
+                for book in books {
+                    if let (Some(id), Some(name)) = (book.series_id.as_deref(), book.series.as_deref()) {
+                        series.entry((name, id)).or_default().push(book);
+                    }
+                }
+
+                for ((name, id), mut books) in series {
+                    books.sort_by(|a, b| {
+                        a.series_number
+                            .unwrap_or(0.0)
+                            .total_cmp(&b.series_number.unwrap_or(0.0))
+                    });
+                    shelves.push(smart_shelf(&format!("series:{id}"), name, books.into_iter()));
+                }
+            }
+            SmartShelfRule::Author => {
+                let mut authors: BTreeMap<String, Vec<&SmartShelfBook>> = BTreeMap::new();
+                for book in books {
+                    let names: Vec<String> = serde_json::from_str(&book.authors).unwrap_or_default();
+                    for name in names {
+                        authors.entry(name).or_default().push(book);
+                    }
+                }
+
+                for (name, books) in authors {
+                    shelves.push(smart_shelf(&format!("author:{name}"), &name, books.into_iter()));
+                }
+            }
+        }
+    }
+
+    shelves
+}
+
+fn smart_shelf<'a>(key: &str, name: &str, books: impl Iterator<Item = &'a SmartShelfBook>) -> SmartShelf {
+    SmartShelf {
+        id: smart_shelf_id(key),
+        name: name.to_string(),
+        books: books.map(|book| book.book_id.clone()).collect(),
+    }
+}
+
+// Smart shelf IDs are derived from the rule that generates them, so they stay the same across syncs
+fn smart_shelf_id(key: &str) -> String {
+    let hash = Sha256::digest(format!("smart-shelf:{key}").as_bytes());
+    let bytes: [u8; 16] = hash[..16]
+        .try_into()
+        .expect("Failed to truncate smart shelf hash");
+    let hex = format!("{:032x}", u128::from_be_bytes(bytes));
+
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
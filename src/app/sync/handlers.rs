@@ -35,7 +35,7 @@ pub async fn device_sync_handler(
         &state.prosa_client,
         since,
         &server_url,
-        state.config.download_token.book_expiration,
+        &state.config,
         &token.api_key,
         &token.device_id,
     )
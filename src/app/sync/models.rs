@@ -1,7 +1,7 @@
 use super::service::unix_millis_to_string;
 use crate::app::{
     metadata::BookMetadata,
-    shelves::models::{DeletedShelfResponse, NewShelfResponse},
+    shelves::models::{ChangedShelfResponse, DeletedShelfResponse, NewShelfResponse, ShelfUpdate},
     state::models::ReadingState,
 };
 use serde::Serialize;
@@ -12,9 +12,19 @@ use std::time::{SystemTime, UNIX_EPOCH};
 pub enum SyncItem {
     Entitlement(NewEntitlementResponse),
     NewShelf(NewShelfResponse),
+    ChangedShelf(ChangedShelfResponse),
     DeletedShelf(DeletedShelfResponse),
 }
 
+impl From<ShelfUpdate> for SyncItem {
+    fn from(update: ShelfUpdate) -> Self {
+        match update {
+            ShelfUpdate::New(response) => SyncItem::NewShelf(response),
+            ShelfUpdate::Changed(response) => SyncItem::ChangedShelf(response),
+        }
+    }
+}
+
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct NewEntitlementResponse {
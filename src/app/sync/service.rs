@@ -1,14 +1,18 @@
 use super::models::NewEntitlementResponse;
 use crate::{
     app::{
-        annotations, covers,
+        annotations, categories, covers, devices,
         error::KoboError,
         metadata::{self, BookMetadata},
-        shelves::models::{DeletedShelfResponse, NewShelfResponse},
+        shelves::{
+            self,
+            models::{DeletedShelfResponse, SentShelves},
+        },
         state::{self, models::ReadingState},
         sync::models::{BookEntitlement, SyncItem},
     },
     client::prosa::Client,
+    config::Configuration,
 };
 use chrono::{DateTime, Utc};
 use sqlx::SqlitePool;
@@ -22,7 +26,7 @@ pub async fn translate_sync(
     client: &Client,
     since: Option<i64>,
     server_url: &str,
-    book_expiration: i64,
+    config: &Configuration,
     api_key: &str,
     device_id: &str,
 ) -> Result<Vec<SyncItem>, KoboError> {
@@ -32,28 +36,50 @@ pub async fn translate_sync(
 
     // Handle books
 
+    let cover_expiration = config.download_token.cover_expiration;
+
     for book_id in &sync_response.book.cover {
-        covers::update_token(pool, book_id, device_id).await;
+        covers::update_token(pool, book_id, cover_expiration, device_id).await;
+        covers::delete_cached_covers(&config.cover_cache.path, book_id).await;
+    }
+
+    for book_id in &sync_response.book.deleted {
+        covers::delete_token(pool, book_id, device_id).await;
+        categories::delete_device_book_categories(pool, book_id, device_id).await;
+        shelves::delete_device_smart_shelf_book(pool, book_id, device_id).await;
     }
 
     let mut books_to_update: HashSet<String> = sync_response.book.file.into_iter().collect();
+    books_to_update.extend(covers::rotate_tokens(pool, cover_expiration, device_id).await);
     books_to_update.extend(sync_response.book.cover);
     books_to_update.extend(sync_response.book.metadata);
 
+    let overridden = metadata::service::get_pending_overrides(pool, client, api_key, device_id).await?;
+    books_to_update.extend(
+        overridden
+            .iter()
+            .filter(|book_id| !sync_response.book.deleted.contains(book_id))
+            .cloned(),
+    );
+
+    for book_id in &sync_response.book.state {
+        if !books_to_update.contains(book_id) && !sync_response.book.deleted.contains(book_id) {
+            shelves::refresh_smart_shelf_book_status(pool, client, book_id, api_key, device_id).await?;
+        }
+    }
+
+    let updated_books: Vec<String> = books_to_update.iter().cloned().collect();
     for book_id in books_to_update {
         let entitlement = BookEntitlement::new(&book_id, false);
         let reading_state = state::service::translate_get_state(client, &book_id, api_key)?;
         let metadata = metadata::service::translate_metadata(
-            pool,
-            client,
-            &book_id,
-            server_url,
-            book_expiration,
-            api_key,
-            device_id,
+            pool, client, &book_id, server_url, config, api_key, device_id,
         )
         .await?;
 
+        let status = &reading_state.status_info.status;
+        shelves::record_smart_shelf_book(pool, &book_id, status, &metadata, device_id).await;
+
         let response =
             SyncItem::Entitlement(NewEntitlementResponse::new(entitlement, reading_state, metadata));
 
@@ -73,28 +99,70 @@ pub async fn translate_sync(
     // Handle annotations
 
     for book_id in sync_response.book.annotations {
-        annotations::service::update_etag(pool, &book_id).await;
+        annotations::service::invalidate_annotations(pool, &book_id, api_key).await;
     }
 
     // Handle shelfs
 
+    let mut sent_shelves = SentShelves::default();
+
     let mut shelfs_to_update: HashSet<String> = sync_response.shelf.metadata.into_iter().collect();
     shelfs_to_update.extend(sync_response.shelf.contents);
 
     for shelf_id in shelfs_to_update {
         let name = client.get_shelf_metadata(&shelf_id, api_key)?.name;
         let books = client.list_books_in_shelf(&shelf_id, api_key)?;
-        let response = SyncItem::NewShelf(NewShelfResponse::new(&shelf_id, &name, &books));
+        let books = shelves::order_shelf_books(pool, &shelf_id, books).await;
+
+        if let Some(update) =
+            shelves::translate_shelf_update(pool, &shelf_id, &name, &books, &mut sent_shelves, device_id)
+                .await
+        {
+            translated_response.push(update.into());
+        }
+    }
+
+    for shelf_id in sync_response.shelf.deleted {
+        sent_shelves.untrack(&shelf_id);
+        shelves::delete_shelf_order(pool, &shelf_id).await;
+        let response = SyncItem::DeletedShelf(DeletedShelfResponse::new(&shelf_id));
 
         translated_response.push(response);
     }
 
-    for shelf_id in sync_response.shelf.deleted {
+    // Handle shared shelves
+
+    let (updates, deleted) =
+        shelves::sync_shared_shelves(pool, client, api_key, &mut sent_shelves, device_id).await?;
+
+    translated_response.extend(updates.into_iter().map(SyncItem::from));
+
+    for shelf_id in deleted {
         let response = SyncItem::DeletedShelf(DeletedShelfResponse::new(&shelf_id));
 
         translated_response.push(response);
     }
 
+    // Handle smart shelves
+
+    let rules = devices::service::get_device_smart_shelf_rules(pool, &config.smart_shelves, device_id).await;
+    let (updates, deleted) =
+        shelves::rebuild_smart_shelves(pool, client, &rules, api_key, &mut sent_shelves, device_id).await?;
+
+    translated_response.extend(updates.into_iter().map(SyncItem::from));
+
+    for shelf_id in deleted {
+        let response = SyncItem::DeletedShelf(DeletedShelfResponse::new(&shelf_id));
+
+        translated_response.push(response);
+    }
+
+    // Save what the device received, now that the whole response is built
+
+    covers::prune_tokens(pool, &updated_books, device_id).await;
+    metadata::service::clear_pending_overrides(pool, &overridden, device_id).await;
+    shelves::save_sent_shelves(pool, sent_shelves, device_id).await;
+
     Ok(translated_response)
 }
 
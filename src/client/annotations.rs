@@ -1,7 +1,11 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::thread;
 use ureq::{Agent, Error};
 
+// Maximum number of annotations fetched from Prosa at the same time
+const FETCH_CONCURRENCY: usize = 8;
+
 pub struct AnnotationsClient {
     pub url: String,
     pub agent: Agent,
@@ -34,6 +38,40 @@ impl AnnotationsClient {
             .read_json::<ProsaAnnotation>()
     }
 
+    pub fn get_annotations(
+        &self,
+        book_id: &str,
+        annotation_ids: &[String],
+        api_key: &str,
+    ) -> Result<Vec<ProsaAnnotation>, Error> {
+        if annotation_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let chunk_size = annotation_ids.len().div_ceil(FETCH_CONCURRENCY);
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = annotation_ids
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|id| self.get_annotation(book_id, id, api_key))
+                            .collect::<Result<Vec<ProsaAnnotation>, Error>>()
+                    })
+                })
+                .collect();
+
+            let mut annotations = Vec::with_capacity(annotation_ids.len());
+            for handle in handles {
+                annotations.extend(handle.join().expect("Failed to fetch annotations")?);
+            }
+
+            Ok(annotations)
+        })
+    }
+
     pub fn add_annotation(
         &self,
         book_id: &str,
@@ -22,6 +22,19 @@ impl BookClient {
         Ok(body)
     }
 
+    pub fn search_books(&self, page: Option<u64>, api_key: &str) -> Result<ProsaPaginatedBooks, Error> {
+        let mut request = self
+            .agent
+            .get(format!("{}/books", self.url))
+            .header("api-key", api_key);
+
+        if let Some(page) = page {
+            request = request.query("page", page.to_string());
+        }
+
+        request.call()?.body_mut().read_json::<ProsaPaginatedBooks>()
+    }
+
     pub fn delete_book(&self, book_id: &str, api_key: &str) -> Result<(), Error> {
         self.agent
             .delete(format!("{}/books/{book_id}", self.url))
@@ -50,3 +63,10 @@ pub struct ProsaBookFileMetadata {
     pub owner_id: String,
     pub file_size: u64,
 }
+
+#[derive(Deserialize, Debug)]
+pub struct ProsaPaginatedBooks {
+    pub book_ids: Vec<String>,
+    pub total_elements: u64,
+    pub current_page: u64,
+}
@@ -6,6 +6,7 @@ pub mod prosa;
 mod shelf;
 mod state;
 mod sync;
+mod user;
 
 pub use annotations::ProsaAnnotation;
 pub use annotations::ProsaAnnotationRequest;
@@ -6,11 +6,12 @@ use super::{
     metadata::{MetadataClient, ProsaMetadata},
     state::StateClient,
     sync::{ProsaSync, SyncClient},
+    user::UserClient,
 };
 use crate::{
     app::AppState,
     client::{
-        book::ProsaBookFileMetadata,
+        book::{ProsaBookFileMetadata, ProsaPaginatedBooks},
         shelf::{ProsaShelfMetadata, ShelfClient},
     },
 };
@@ -54,6 +55,7 @@ pub struct Client {
     cover_client: CoverClient,
     annotations_client: AnnotationsClient,
     shelf_client: ShelfClient,
+    user_client: UserClient,
 }
 
 impl Client {
@@ -90,6 +92,10 @@ impl Client {
                 url: url.clone(),
                 agent: agent.clone(),
             },
+            user_client: UserClient {
+                url: url.clone(),
+                agent: agent.clone(),
+            },
         }
     }
 
@@ -145,6 +151,33 @@ impl Client {
         Ok(result)
     }
 
+    pub fn search_books(&self, page: Option<u64>, api_key: &str) -> Result<ProsaPaginatedBooks, ClientError> {
+        let result = self.book_client.search_books(page, api_key)?;
+        Ok(result)
+    }
+
+    // Prosa lists books a page at a time, in pages of its own default size
+    pub fn list_books(&self, api_key: &str) -> Result<Vec<String>, ClientError> {
+        let mut book_ids = Vec::new();
+        let mut page = None;
+
+        loop {
+            let response = self.book_client.search_books(page, api_key)?;
+            if response.book_ids.is_empty() {
+                break;
+            }
+
+            book_ids.extend(response.book_ids);
+            if book_ids.len() as u64 >= response.total_elements {
+                break;
+            }
+
+            page = Some(response.current_page + 1);
+        }
+
+        Ok(book_ids)
+    }
+
     pub fn delete_book(&self, book_id: &str, api_key: &str) -> Result<(), ClientError> {
         self.book_client.delete_book(book_id, api_key)?;
         Ok(())
@@ -172,6 +205,18 @@ impl Client {
         Ok(result)
     }
 
+    pub fn get_annotations(
+        &self,
+        book_id: &str,
+        annotation_ids: &[String],
+        api_key: &str,
+    ) -> Result<Vec<ProsaAnnotation>, ClientError> {
+        let result = self
+            .annotations_client
+            .get_annotations(book_id, annotation_ids, api_key)?;
+        Ok(result)
+    }
+
     pub fn add_annotation(
         &self,
         book_id: &str,
@@ -262,6 +307,12 @@ impl Client {
             .delete_book_from_shelf(shelf_id, book_id, api_key)?;
         Ok(())
     }
+
+    // Prosa only lists a user's API keys to that user, so this verifies an API key acts as the user
+    pub fn verify_user(&self, user_id: &str, api_key: &str) -> Result<(), ClientError> {
+        self.user_client.list_api_keys(user_id, api_key)?;
+        Ok(())
+    }
 }
 
 impl FromRef<AppState> for Arc<Client> {
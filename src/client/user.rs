@@ -0,0 +1,17 @@
+use ureq::{Agent, Error};
+
+pub struct UserClient {
+    pub url: String,
+    pub agent: Agent,
+}
+
+impl UserClient {
+    pub fn list_api_keys(&self, user_id: &str, api_key: &str) -> Result<(), Error> {
+        self.agent
+            .get(format!("{}/users/{user_id}/keys", self.url))
+            .header("api-key", api_key)
+            .call()?;
+
+        Ok(())
+    }
+}
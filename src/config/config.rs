@@ -1,7 +1,7 @@
 use crate::app::AppState;
 use axum::extract::FromRef;
 use config::{Config, ConfigError, File};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 #[derive(Default, Deserialize)]
@@ -12,6 +12,10 @@ pub struct Configuration {
     pub auth: Auth,
     pub prosa: Prosa,
     pub download_token: DownloadToken,
+    pub cover_cache: CoverCache,
+    pub categories: Categories,
+    pub annotations: Annotations,
+    pub smart_shelves: SmartShelves,
 }
 
 #[derive(Default, Deserialize)]
@@ -47,6 +51,49 @@ pub struct Prosa {
 #[serde(default)]
 pub struct DownloadToken {
     pub book_expiration: i64,
+    pub cover_expiration: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct CoverCache {
+    pub path: String,
+    pub max_age: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Annotations {
+    pub page_size: usize,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct SmartShelves {
+    pub rules: Vec<SmartShelfRule>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SmartShelfRule {
+    CurrentlyReading,
+    Unread,
+    FinishedThisYear,
+    Series,
+    Author,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct Categories {
+    pub mappings: Vec<CategoryMapping>,
+}
+
+#[derive(Deserialize)]
+pub struct CategoryMapping {
+    pub id: String,
+    pub name: String,
+    pub genres: Vec<String>,
 }
 
 #[derive(Deserialize, Clone)]
@@ -84,7 +131,25 @@ impl Default for Prosa {
 
 impl Default for DownloadToken {
     fn default() -> Self {
-        Self { book_expiration: 60 }
+        Self {
+            book_expiration: 60,
+            cover_expiration: 604800,
+        }
+    }
+}
+
+impl Default for CoverCache {
+    fn default() -> Self {
+        Self {
+            path: "persistence/covers".to_string(),
+            max_age: 86400,
+        }
+    }
+}
+
+impl Default for Annotations {
+    fn default() -> Self {
+        Self { page_size: 100 }
     }
 }
 
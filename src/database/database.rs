@@ -1,4 +1,4 @@
-use super::tables::{clear_tables, create_tables};
+use super::tables::{clear_tables, create_tables, migrate_tables};
 use crate::app::AppState;
 use axum::extract::FromRef;
 use sqlx::{Pool, Sqlite, SqlitePool, sqlite::SqliteConnectOptions};
@@ -13,6 +13,7 @@ pub async fn init(filename: &str) -> Pool<Sqlite> {
     let pool = SqlitePool::connect_with(db_options).await.unwrap();
 
     create_tables(&pool).await;
+    migrate_tables(&pool).await;
 
     pool
 }
@@ -27,6 +28,7 @@ pub async fn debug_init(filename: &str) -> Pool<Sqlite> {
 
     clear_tables(&pool).await;
     create_tables(&pool).await;
+    migrate_tables(&pool).await;
 
     pool
 }
@@ -1,5 +1,6 @@
 use sqlx::SqlitePool;
 
+#[allow(clippy::too_many_lines)]
 pub async fn create_tables(pool: &SqlitePool) {
     sqlx::query(
         r"
@@ -25,12 +26,152 @@ pub async fn create_tables(pool: &SqlitePool) {
             book_id TEXT NOT NULL,
             token TEXT NOT NULL,
             device_id TEXT NOT NULL,
+            expiration BIGINT NOT NULL,
             PRIMARY KEY(book_id, token)
         );
 
-        CREATE TABLE IF NOT EXISTS etags (
-            book_id TEXT PRIMARY KEY NOT NULL,
-            etag TEXT NOT NULL
+        CREATE TABLE IF NOT EXISTS categories (
+            category_id TEXT PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS book_categories (
+            book_id TEXT NOT NULL,
+            category_id TEXT NOT NULL,
+            device_id TEXT NOT NULL,
+            PRIMARY KEY(book_id, category_id, device_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS series (
+            series_id TEXT PRIMARY KEY NOT NULL,
+            title TEXT NOT NULL,
+            author TEXT NOT NULL,
+            UNIQUE(title, author)
+        );
+
+        CREATE TABLE IF NOT EXISTS metadata_overrides (
+            api_key TEXT NOT NULL,
+            book_id TEXT NOT NULL,
+            title TEXT,
+            subtitle TEXT,
+            series_title TEXT,
+            series_number REAL,
+            sort_author TEXT,
+            description TEXT,
+            PRIMARY KEY(api_key, book_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS pending_overrides (
+            book_id TEXT NOT NULL,
+            device_id TEXT NOT NULL,
+            PRIMARY KEY(book_id, device_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS annotation_extras (
+            book_id TEXT NOT NULL,
+            annotation_id TEXT NOT NULL,
+            created TEXT NOT NULL,
+            last_modified TEXT NOT NULL,
+            color TEXT,
+            style TEXT,
+            text TEXT,
+            bookmark BOOLEAN NOT NULL DEFAULT 0,
+            start_path TEXT,
+            end_path TEXT,
+            kobo_id TEXT,
+            PRIMARY KEY(book_id, annotation_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS annotation_tombstones (
+            book_id TEXT NOT NULL,
+            annotation_id TEXT NOT NULL,
+            kobo_id TEXT,
+            deleted TEXT NOT NULL,
+            PRIMARY KEY(book_id, annotation_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS device_activity (
+            device_id TEXT NOT NULL,
+            timestamp BIGINT NOT NULL,
+            event TEXT NOT NULL,
+            book_id TEXT NOT NULL,
+            annotation_id TEXT NOT NULL,
+            detail TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS annotation_cache (
+            book_id TEXT NOT NULL,
+            api_key TEXT NOT NULL,
+            etag TEXT NOT NULL,
+            annotations TEXT NOT NULL,
+            PRIMARY KEY(book_id, api_key)
+        );
+
+        CREATE TABLE IF NOT EXISTS device_shelves (
+            device_id TEXT NOT NULL,
+            shelf_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            books TEXT NOT NULL,
+            PRIMARY KEY(device_id, shelf_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS shelf_order (
+            shelf_id TEXT NOT NULL,
+            book_id TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            PRIMARY KEY(shelf_id, book_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS shelf_shares (
+            owner_device_id TEXT NOT NULL,
+            shelf_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            PRIMARY KEY(owner_device_id, shelf_id, user_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS accepted_shelf_shares (
+            device_id TEXT NOT NULL,
+            shelf_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            PRIMARY KEY(device_id, shelf_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS shared_shelves (
+            device_id TEXT NOT NULL,
+            shelf_id TEXT NOT NULL,
+            PRIMARY KEY(device_id, shelf_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS smart_shelf_rules (
+            device_id TEXT PRIMARY KEY NOT NULL,
+            rules TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS smart_shelf_books (
+            device_id TEXT NOT NULL,
+            book_id TEXT NOT NULL,
+            status TEXT NOT NULL,
+            finished BIGINT,
+            series_id TEXT,
+            series TEXT,
+            series_number REAL,
+            authors TEXT NOT NULL,
+            PRIMARY KEY(device_id, book_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS seeded_smart_shelves (
+            device_id TEXT PRIMARY KEY NOT NULL,
+            next_page INTEGER,
+            complete BOOLEAN NOT NULL DEFAULT 0
+        );
+
+        CREATE TABLE IF NOT EXISTS smart_shelves (
+            device_id TEXT NOT NULL,
+            shelf_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            books TEXT NOT NULL,
+            resend BOOLEAN NOT NULL DEFAULT 0,
+            PRIMARY KEY(device_id, shelf_id)
         );
         ",
     )
@@ -39,6 +180,41 @@ pub async fn create_tables(pool: &SqlitePool) {
     .expect("Failed to create tables");
 }
 
+pub async fn migrate_tables(pool: &SqlitePool) {
+    // Cover tokens created before they had an expiration are treated as already expired
+    add_column_if_missing(pool, "cover_tokens", "expiration", "BIGINT NOT NULL DEFAULT 0").await;
+
+    // Annotation etags are now stored with the cached annotations of each user
+    sqlx::query("DROP TABLE IF EXISTS etags")
+        .execute(pool)
+        .await
+        .expect("Failed to drop etags table");
+}
+
+async fn add_column_if_missing(pool: &SqlitePool, table: &str, column: &str, definition: &str) {
+    let exists: bool = sqlx::query_scalar(
+        r"
+        SELECT COUNT(*) > 0
+        FROM pragma_table_info($1)
+        WHERE name = $2
+        ",
+    )
+    .bind(table)
+    .bind(column)
+    .fetch_one(pool)
+    .await
+    .expect("Failed to inspect table");
+
+    if exists {
+        return;
+    }
+
+    sqlx::query(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"))
+        .execute(pool)
+        .await
+        .expect("Failed to migrate table");
+}
+
 pub async fn clear_tables(pool: &SqlitePool) {
     sqlx::query(
         r"
@@ -46,7 +222,24 @@ pub async fn clear_tables(pool: &SqlitePool) {
         DROP TABLE IF EXISTS cover_tokens;
         DROP TABLE IF EXISTS linked_devices;
         DROP TABLE IF EXISTS unlinked_devices;
-        DROP TABLE IF EXISTS etags;
+        DROP TABLE IF EXISTS annotation_cache;
+        DROP TABLE IF EXISTS annotation_extras;
+        DROP TABLE IF EXISTS annotation_tombstones;
+        DROP TABLE IF EXISTS device_activity;
+        DROP TABLE IF EXISTS categories;
+        DROP TABLE IF EXISTS book_categories;
+        DROP TABLE IF EXISTS series;
+        DROP TABLE IF EXISTS metadata_overrides;
+        DROP TABLE IF EXISTS pending_overrides;
+        DROP TABLE IF EXISTS device_shelves;
+        DROP TABLE IF EXISTS shelf_order;
+        DROP TABLE IF EXISTS shelf_shares;
+        DROP TABLE IF EXISTS accepted_shelf_shares;
+        DROP TABLE IF EXISTS shared_shelves;
+        DROP TABLE IF EXISTS smart_shelf_rules;
+        DROP TABLE IF EXISTS smart_shelf_books;
+        DROP TABLE IF EXISTS seeded_smart_shelves;
+        DROP TABLE IF EXISTS smart_shelves;
         ",
     )
     .execute(pool)
@@ -1,6 +1,7 @@
+import { randomUUID } from 'crypto';
 import { DEVICE_NOT_LINKED, UNAUTHENTICATED } from '../utils/common';
-import { addAnnotation, annotationRequest, checkForChanges, deleteAnnotation, getAnnotations, updateAnnotation } from '../utils/kobont/annotations';
-import { authDevice, linkDevice } from '../utils/kobont/devices';
+import { addAnnotation, annotationRequest, checkForChanges, deleteAnnotation, exportAnnotations, getAnnotations, INVALID_PAGE_TOKEN, MISSING_API_KEY, patchAnnotations, updateAnnotation } from '../utils/kobont/annotations';
+import { authDevice, DEVICE_NOT_FOUND, getDeviceActivity, linkDevice } from '../utils/kobont/devices';
 import { sync } from '../utils/kobont/sync';
 import { addAnnotation as addProsaAnnotation, ALICE_NOTE, listAnnotations as listProsaAnnotations } from '../utils/prosa/annotations';
 import { uploadBook } from '../utils/prosa/books';
@@ -24,7 +25,16 @@ describe('Check for changes', () => {
     const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
     expect(linkResponse.status).toBe(200);
 
-    let checkForChangesResponse = await checkForChanges([{ ContentId: uploadResponse.text, etag: 'etag' }]);
+    // An etag the middleware never handed out is always outdated
+    let checkForChangesResponse = await checkForChanges([{ ContentId: uploadResponse.text, etag: 'etag' }], authResponse.body.AccessToken);
+    expect(checkForChangesResponse.status).toBe(200);
+    expect(checkForChangesResponse.body).toEqual([uploadResponse.text]);
+
+    const getAnnotationsResponse = await getAnnotations(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getAnnotationsResponse.status).toBe(200);
+    const etag = getAnnotationsResponse.headers['etag'];
+
+    checkForChangesResponse = await checkForChanges([{ ContentId: uploadResponse.text, etag: etag }], authResponse.body.AccessToken);
     expect(checkForChangesResponse.status).toBe(200);
     expect(checkForChangesResponse.body).toEqual([]);
 
@@ -32,7 +42,7 @@ describe('Check for changes', () => {
     expect(addAnnotationResponse.status).toBe(200);
 
     // Won't detect changes until sync
-    checkForChangesResponse = await checkForChanges([{ ContentId: uploadResponse.text, etag: 'etag' }]);
+    checkForChangesResponse = await checkForChanges([{ ContentId: uploadResponse.text, etag: etag }], authResponse.body.AccessToken);
     expect(checkForChangesResponse.status).toBe(200);
     expect(checkForChangesResponse.body).toEqual([]);
 
@@ -40,13 +50,97 @@ describe('Check for changes', () => {
     expect(syncResponse.status).toBe(200);
 
     // Should have been detected now
-    checkForChangesResponse = await checkForChanges([
-      { ContentId: uploadResponse.text, etag: 'etag' },
-      { ContentId: 'non-existent', etag: 'etag2' }
-    ]);
+    checkForChangesResponse = await checkForChanges(
+      [
+        { ContentId: uploadResponse.text, etag: etag },
+        { ContentId: 'non-existent', etag: 'etag2' }
+      ],
+      authResponse.body.AccessToken
+    );
     expect(checkForChangesResponse.status).toBe(200);
     expect(checkForChangesResponse.body).toEqual([uploadResponse.text]);
   });
+
+  test('Multiple devices', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: firstAuthResponse, deviceId: firstDeviceId } = await authDevice();
+    expect(firstAuthResponse.status).toBe(200);
+    const { response: secondAuthResponse, deviceId: secondDeviceId } = await authDevice();
+    expect(secondAuthResponse.status).toBe(200);
+
+    expect((await linkDevice(firstDeviceId, createApiKeyResponse.body.key)).status).toBe(200);
+    expect((await linkDevice(secondDeviceId, createApiKeyResponse.body.key)).status).toBe(200);
+
+    const getAnnotationsResponse = await getAnnotations(uploadResponse.text, firstAuthResponse.body.AccessToken);
+    expect(getAnnotationsResponse.status).toBe(200);
+    const etag = getAnnotationsResponse.headers['etag'];
+
+    const addAnnotationResponse = await addProsaAnnotation(uploadResponse.text, ALICE_NOTE, { jwt: registerResponse.body.jwt_token });
+    expect(addAnnotationResponse.status).toBe(200);
+
+    const syncResponse = await sync(undefined, firstAuthResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+
+    // The second device never synced the change itself, but must still see it
+    for (const jwt of [secondAuthResponse.body.AccessToken, firstAuthResponse.body.AccessToken]) {
+      const checkForChangesResponse = await checkForChanges([{ ContentId: uploadResponse.text, etag: etag }], jwt);
+      expect(checkForChangesResponse.status).toBe(200);
+      expect(checkForChangesResponse.body).toEqual([uploadResponse.text]);
+    }
+  });
+
+  test('Multiple users', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: otherRegisterResponse } = await registerUser();
+    expect(otherRegisterResponse.status).toBe(200);
+
+    const otherApiKeyResponse = await createApiKey(otherRegisterResponse.body.user_id, 'Test Key', ['Read'], undefined, { jwt: otherRegisterResponse.body.jwt_token });
+    expect(otherApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+    const { response: otherAuthResponse, deviceId: otherDeviceId } = await authDevice();
+    expect(otherAuthResponse.status).toBe(200);
+
+    expect((await linkDevice(deviceId, createApiKeyResponse.body.key)).status).toBe(200);
+    expect((await linkDevice(otherDeviceId, otherApiKeyResponse.body.key)).status).toBe(200);
+
+    const getAnnotationsResponse = await getAnnotations(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getAnnotationsResponse.status).toBe(200);
+    const etag = getAnnotationsResponse.headers['etag'];
+
+    // Another user asking about the same book must not affect the owner's etag
+    const otherCheckResponse = await checkForChanges([{ ContentId: uploadResponse.text, etag: 'etag' }], otherAuthResponse.body.AccessToken);
+    expect(otherCheckResponse.status).toBe(200);
+
+    const checkForChangesResponse = await checkForChanges([{ ContentId: uploadResponse.text, etag: etag }], authResponse.body.AccessToken);
+    expect(checkForChangesResponse.status).toBe(200);
+    expect(checkForChangesResponse.body).toEqual([]);
+  });
+
+  test('No auth', async () => {
+    const checkForChangesResponse = await checkForChanges([{ ContentId: 'non-existent', etag: 'etag' }]);
+    expect(checkForChangesResponse.status).toBe(401);
+    expect(checkForChangesResponse.body.message).toBe(UNAUTHENTICATED);
+  });
 });
 
 describe('Get annotations', () => {
@@ -71,16 +165,132 @@ describe('Get annotations', () => {
     expect(getAnnotationsResponse.status).toBe(200);
     expect(getAnnotationsResponse.body).toEqual({ annotations: [], nextPageOffsetToken: null });
 
+    const etag = getAnnotationsResponse.headers['etag'];
+
     const addAnnotationResponse = await addProsaAnnotation(uploadResponse.text, ALICE_NOTE, { jwt: registerResponse.body.jwt_token });
     expect(addAnnotationResponse.status).toBe(200);
 
+    // Served from the cache until sync detects the change
+    getAnnotationsResponse = await getAnnotations(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getAnnotationsResponse.status).toBe(200);
+    expect(getAnnotationsResponse.body).toEqual({ annotations: [], nextPageOffsetToken: null });
+    expect(getAnnotationsResponse.headers['etag']).toBe(etag);
+
+    const syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+
     getAnnotationsResponse = await getAnnotations(uploadResponse.text, authResponse.body.AccessToken);
     expect(getAnnotationsResponse.status).toBe(200);
+    expect(getAnnotationsResponse.headers['etag']).not.toBe(etag);
     expect(getAnnotationsResponse.body.annotations).toHaveLength(1);
     expect(getAnnotationsResponse.body.annotations[0].noteText).toEqual(ALICE_NOTE.note);
     expect(getAnnotationsResponse.headers['ETag']).not.toBeNull();
   });
 
+  test('Pagination', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    for (const note of ['First', 'Second', 'Third']) {
+      const addAnnotationResponse = await addProsaAnnotation(uploadResponse.text, { ...ALICE_NOTE, note: note }, { jwt: registerResponse.body.jwt_token });
+      expect(addAnnotationResponse.status).toBe(200);
+    }
+
+    let getAnnotationsResponse = await getAnnotations(uploadResponse.text, authResponse.body.AccessToken, 2);
+    expect(getAnnotationsResponse.status).toBe(200);
+    expect(getAnnotationsResponse.body.annotations).toHaveLength(2);
+    expect(getAnnotationsResponse.body.nextPageOffsetToken).not.toBeNull();
+
+    const firstPage = getAnnotationsResponse.body.annotations.map((a: any) => a.id);
+
+    getAnnotationsResponse = await getAnnotations(uploadResponse.text, authResponse.body.AccessToken, 2, getAnnotationsResponse.body.nextPageOffsetToken);
+    expect(getAnnotationsResponse.status).toBe(200);
+    expect(getAnnotationsResponse.body.annotations).toHaveLength(1);
+    expect(getAnnotationsResponse.body.nextPageOffsetToken).toBeNull();
+
+    const secondPage = getAnnotationsResponse.body.annotations.map((a: any) => a.id);
+
+    const listAnnotationsResponse = await listProsaAnnotations(uploadResponse.text, { jwt: registerResponse.body.jwt_token });
+    expect(listAnnotationsResponse.status).toBe(200);
+    expect([...firstPage, ...secondPage].sort()).toEqual([...listAnnotationsResponse.body].sort());
+
+    getAnnotationsResponse = await getAnnotations(uploadResponse.text, authResponse.body.AccessToken, 2, 'invalid token');
+    expect(getAnnotationsResponse.status).toBe(400);
+    expect(getAnnotationsResponse.body.message).toBe(INVALID_PAGE_TOKEN);
+  });
+
+  test('Highlighted text', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const addAnnotationResponse = await addProsaAnnotation(uploadResponse.text, ALICE_NOTE, { jwt: registerResponse.body.jwt_token });
+    expect(addAnnotationResponse.status).toBe(200);
+
+    // Polling for annotations doesn't download the book
+    let getAnnotationsResponse = await getAnnotations(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getAnnotationsResponse.status).toBe(200);
+    expect(getAnnotationsResponse.body.annotations).toHaveLength(1);
+    expect(getAnnotationsResponse.body.annotations[0].highlightedText).toBeUndefined();
+
+    // Exporting captures the text of annotations made outside of a Kobo
+    const exportResponse = await exportAnnotations(createApiKeyResponse.body.key, 'json', uploadResponse.text);
+    expect(exportResponse.status).toBe(200);
+
+    const exportedText = exportResponse.body[0].annotations[0].text;
+    expect(typeof exportedText).toBe('string');
+    expect(exportedText.length).toBeGreaterThan(0);
+
+    getAnnotationsResponse = await getAnnotations(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getAnnotationsResponse.status).toBe(200);
+    expect(getAnnotationsResponse.body.annotations[0].highlightedText).toBe(exportedText);
+
+    // Annotations patched by a device get their text right away
+    const addAnnotationRequest: annotationRequest = {
+      startChar: 0,
+      startTag: 'kobo.74.1',
+      endChar: 5,
+      endTag: 'kobo.74.1',
+      source: 'OEBPS/229714655232534212_11-h-10.htm.xhtml'
+    };
+
+    const patchResponse = await addAnnotation(uploadResponse.text, addAnnotationRequest, authResponse.body.AccessToken);
+    expect(patchResponse.status).toBe(204);
+
+    getAnnotationsResponse = await getAnnotations(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getAnnotationsResponse.status).toBe(200);
+    expect(getAnnotationsResponse.body.annotations).toHaveLength(2);
+
+    const patched = getAnnotationsResponse.body.annotations.find((annotation: any) => annotation.type === 'highlight');
+    expect(typeof patched.highlightedText).toBe('string');
+    expect(patched.highlightedText.length).toBeGreaterThan(0);
+  });
+
   test('Non-existent book', async () => {
     const { response: registerResponse } = await registerUser();
     expect(registerResponse.status).toBe(200);
@@ -176,6 +386,224 @@ describe('Patch annotations', () => {
     expect(getAnnotationsResponse.headers['ETag']).not.toBeNull();
   });
 
+  test('Timestamps, colors and styles', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read', 'Update'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    let annotationRequest: annotationRequest = {
+      startChar: 7,
+      startTag: 'kobo.74.1',
+      endChar: 4,
+      endTag: 'kobo.74.2',
+      source: 'OEBPS/229714655232534212_11-h-10.htm.xhtml',
+      clientLastModifiedUtc: '2025-01-01T10:00:00.0000000Z',
+      highlightColor: 'yellow',
+      highlightStyle: 'underline'
+    };
+
+    const addAnnotationResponse = await addAnnotation(uploadResponse.text, annotationRequest, authResponse.body.AccessToken);
+    expect(addAnnotationResponse.status).toBe(204);
+
+    let getAnnotationsResponse = await getAnnotations(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getAnnotationsResponse.status).toBe(200);
+    expect(getAnnotationsResponse.body.annotations).toHaveLength(1);
+    expect(getAnnotationsResponse.body.annotations[0].clientCreatedUtc).toBe('2025-01-01T10:00:00.0000000Z');
+    expect(getAnnotationsResponse.body.annotations[0].clientLastModifiedUtc).toBe('2025-01-01T10:00:00.0000000Z');
+    expect(getAnnotationsResponse.body.annotations[0].highlightColor).toBe('yellow');
+    expect(getAnnotationsResponse.body.annotations[0].highlightStyle).toBe('underline');
+
+    // Fetching again must not report the annotation as modified
+    getAnnotationsResponse = await getAnnotations(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getAnnotationsResponse.body.annotations[0].clientLastModifiedUtc).toBe('2025-01-01T10:00:00.0000000Z');
+
+    const annotationId = getAnnotationsResponse.body.annotations[0].id;
+    annotationRequest = { ...annotationRequest, clientLastModifiedUtc: '2025-02-01T10:00:00.0000000Z', highlightColor: 'blue' };
+
+    const updateAnnotationResponse = await updateAnnotation(uploadResponse.text, annotationId, annotationRequest, authResponse.body.AccessToken);
+    expect(updateAnnotationResponse.status).toBe(204);
+
+    getAnnotationsResponse = await getAnnotations(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getAnnotationsResponse.status).toBe(200);
+    expect(getAnnotationsResponse.body.annotations[0].clientCreatedUtc).toBe('2025-01-01T10:00:00.0000000Z');
+    expect(getAnnotationsResponse.body.annotations[0].clientLastModifiedUtc).toBe('2025-02-01T10:00:00.0000000Z');
+    expect(getAnnotationsResponse.body.annotations[0].highlightColor).toBe('blue');
+  });
+
+  test('Location paths', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read', 'Update'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const annotation = (id: string, startPath: string, endPath: string, endChar: number) => ({
+      clientLastModifiedUtc: '2025-01-01T10:00:00.0000000Z',
+      id: id,
+      location: {
+        span: {
+          chapterFilename: 'OEBPS/229714655232534212_11-h-10.htm.xhtml',
+          chapterProgress: 0,
+          endChar: endChar,
+          endPath: endPath,
+          startChar: 7,
+          startPath: startPath
+        }
+      },
+      type: 'highlight'
+    });
+
+    const xpath = annotation('xpath', "/html/body/div[1]/span[@id='kobo.74.1']", "/html/body/div[1]/span[@id='kobo.74.2']", 4);
+    const cfi = annotation('cfi', 'epubcfi(/6/4!/4/2[kobo.75.1]/1:7)', 'epubcfi(/6/4!/4/2[kobo.75.2]/1:4)', 4);
+
+    const patchResponse = await patchAnnotations(uploadResponse.text, { updatedAnnotations: [xpath, cfi] }, authResponse.body.AccessToken);
+    expect(patchResponse.status).toBe(204);
+
+    const getAnnotationsResponse = await getAnnotations(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getAnnotationsResponse.status).toBe(200);
+    expect(getAnnotationsResponse.body.annotations).toHaveLength(2);
+
+    // Locations are returned in the form the device sent them
+    const paths = getAnnotationsResponse.body.annotations.map((a: any) => [a.location.span.startPath, a.location.span.endPath]).sort();
+    expect(paths).toEqual([
+      [cfi.location.span.startPath, cfi.location.span.endPath],
+      [xpath.location.span.startPath, xpath.location.span.endPath]
+    ]);
+  });
+
+  test('Invalid locations', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read', 'Update'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const annotation = (id: string, startPath: string, endPath: string, endChar: number) => ({
+      clientLastModifiedUtc: '2025-01-01T10:00:00.0000000Z',
+      id: id,
+      location: {
+        span: {
+          chapterFilename: 'OEBPS/229714655232534212_11-h-10.htm.xhtml',
+          chapterProgress: 0,
+          endChar: endChar,
+          endPath: endPath,
+          startChar: 7,
+          startPath: startPath
+        }
+      },
+      type: 'highlight'
+    });
+
+    const valid = annotation('valid', 'span#kobo\\.74\\.1', 'span#kobo\\.74\\.2', 4);
+    const invalid = [
+      { annotation: annotation('unsupported', '/html/body/p[3]', '/html/body/p[3]', 4), detail: 'UnsupportedPath' },
+      { annotation: annotation('empty', 'span#kobo\\.74\\.1', 'span#kobo\\.74\\.2', 0), detail: 'InvalidRange' },
+      { annotation: { id: 'no-span', clientLastModifiedUtc: 'placeholder', location: {}, type: 'highlight' }, detail: 'MissingSpan' }
+    ];
+
+    // Invalid annotations are reported without blocking the rest of the patch
+    const patchResponse = await patchAnnotations(uploadResponse.text, { updatedAnnotations: [valid, ...invalid.map(({ annotation }) => annotation)] }, authResponse.body.AccessToken);
+    expect(patchResponse.status).toBe(204);
+
+    const getAnnotationsResponse = await getAnnotations(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getAnnotationsResponse.status).toBe(200);
+    expect(getAnnotationsResponse.body.annotations).toHaveLength(1);
+
+    const activityResponse = await getDeviceActivity(deviceId, createApiKeyResponse.body.key);
+    expect(activityResponse.status).toBe(200);
+    expect(activityResponse.body).toHaveLength(invalid.length);
+    for (const { annotation, detail } of invalid) {
+      const activity = activityResponse.body.find((entry: { annotation_id: string }) => entry.annotation_id === annotation.id);
+      expect(activity.event).toBe('InvalidAnnotationLocation');
+      expect(activity.book_id).toBe(uploadResponse.text);
+      expect(activity.detail).toBe(detail);
+    }
+  });
+
+  test('Bookmarks', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read', 'Update'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const location = {
+      span: {
+        chapterFilename: 'OEBPS/229714655232534212_11-h-10.htm.xhtml',
+        chapterProgress: 0.5,
+        startChar: 7,
+        startPath: 'span#kobo\\.74\\.1'
+      }
+    };
+
+    const patchResponse = await patchAnnotations(
+      uploadResponse.text,
+      {
+        updatedAnnotations: [
+          { clientLastModifiedUtc: '2025-01-01T10:00:00.0000000Z', id: 'bookmark', location: location, type: 'dogear' },
+          // Only bookmarks may omit the end of their location
+          { clientLastModifiedUtc: '2025-01-01T10:00:00.0000000Z', id: 'highlight', location: location, type: 'highlight' }
+        ]
+      },
+      authResponse.body.AccessToken
+    );
+    expect(patchResponse.status).toBe(204);
+
+    const getAnnotationsResponse = await getAnnotations(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getAnnotationsResponse.status).toBe(200);
+    expect(getAnnotationsResponse.body.annotations).toHaveLength(1);
+
+    const bookmark = getAnnotationsResponse.body.annotations[0];
+    expect(bookmark.type).toBe('dogear');
+    expect(bookmark.highlightedText).toBeUndefined();
+    expect(bookmark.location.span.startPath).toBe(location.span.startPath);
+    expect(bookmark.location.span.startChar).toBe(7);
+    expect(bookmark.location.span.endPath).toBeUndefined();
+    expect(bookmark.location.span.endChar).toBeUndefined();
+  });
+
   test('Update annotation', async () => {
     const { response: registerResponse } = await registerUser();
     expect(registerResponse.status).toBe(200);
@@ -360,3 +788,225 @@ describe('Patch annotations', () => {
     expect(addAnnotationResponse.body.message).toBe(DEVICE_NOT_LINKED);
   });
 });
+
+describe('Annotation conflicts', () => {
+  async function setup() {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read', 'Update'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: firstAuthResponse, deviceId: firstDeviceId } = await authDevice();
+    expect(firstAuthResponse.status).toBe(200);
+    const { response: secondAuthResponse, deviceId: secondDeviceId } = await authDevice();
+    expect(secondAuthResponse.status).toBe(200);
+
+    expect((await linkDevice(firstDeviceId, createApiKeyResponse.body.key)).status).toBe(200);
+    expect((await linkDevice(secondDeviceId, createApiKeyResponse.body.key)).status).toBe(200);
+
+    const annotation: annotationRequest = {
+      startChar: 7,
+      startTag: 'kobo.74.1',
+      endChar: 4,
+      endTag: 'kobo.74.2',
+      source: 'OEBPS/229714655232534212_11-h-10.htm.xhtml',
+      id: randomUUID(),
+      note: 'First version',
+      clientLastModifiedUtc: '2025-01-02T10:00:00.0000000Z'
+    };
+
+    const addAnnotationResponse = await addAnnotation(uploadResponse.text, annotation, firstAuthResponse.body.AccessToken);
+    expect(addAnnotationResponse.status).toBe(204);
+
+    const getAnnotationsResponse = await getAnnotations(uploadResponse.text, firstAuthResponse.body.AccessToken);
+    expect(getAnnotationsResponse.status).toBe(200);
+    const annotationId = getAnnotationsResponse.body.annotations[0].id;
+    // Devices keep referring to the annotations they create by their own id
+    expect(annotationId).toBe(annotation.id);
+
+    return {
+      bookId: uploadResponse.text,
+      apiKey: createApiKeyResponse.body.key,
+      first: firstAuthResponse.body.AccessToken,
+      second: secondAuthResponse.body.AccessToken,
+      firstDeviceId,
+      secondDeviceId,
+      annotation,
+      annotationId
+    };
+  }
+
+  test('Stale update', async () => {
+    const { bookId, apiKey, first, second, secondDeviceId, annotation, annotationId } = await setup();
+
+    const staleResponse = await updateAnnotation(bookId, annotationId, { ...annotation, note: 'Stale version', clientLastModifiedUtc: '2025-01-01T10:00:00.0000000Z' }, second);
+    expect(staleResponse.status).toBe(204);
+
+    let getAnnotationsResponse = await getAnnotations(bookId, first);
+    expect(getAnnotationsResponse.body.annotations[0].noteText).toBe('First version');
+
+    const activityResponse = await getDeviceActivity(secondDeviceId, apiKey);
+    expect(activityResponse.status).toBe(200);
+    expect(activityResponse.body).toHaveLength(1);
+    expect(activityResponse.body[0].event).toBe('StaleAnnotationUpdate');
+    expect(activityResponse.body[0].book_id).toBe(bookId);
+    expect(activityResponse.body[0].annotation_id).toBe(annotationId);
+
+    const newerResponse = await updateAnnotation(bookId, annotationId, { ...annotation, note: 'Newer version', clientLastModifiedUtc: '2025-01-03T10:00:00.0000000Z' }, second);
+    expect(newerResponse.status).toBe(204);
+
+    getAnnotationsResponse = await getAnnotations(bookId, first);
+    expect(getAnnotationsResponse.body.annotations[0].noteText).toBe('Newer version');
+  });
+
+  test('Stale update from the creating device', async () => {
+    const { bookId, apiKey, first, firstDeviceId, annotation } = await setup();
+
+    const staleResponse = await updateAnnotation(bookId, annotation.id!, { ...annotation, note: 'Stale version', clientLastModifiedUtc: '2025-01-01T10:00:00.0000000Z' }, first);
+    expect(staleResponse.status).toBe(204);
+
+    const getAnnotationsResponse = await getAnnotations(bookId, first);
+    expect(getAnnotationsResponse.body.annotations).toHaveLength(1);
+    expect(getAnnotationsResponse.body.annotations[0].noteText).toBe('First version');
+
+    const activityResponse = await getDeviceActivity(firstDeviceId, apiKey);
+    expect(activityResponse.status).toBe(200);
+    expect(activityResponse.body).toHaveLength(1);
+    expect(activityResponse.body[0].event).toBe('StaleAnnotationUpdate');
+    expect(activityResponse.body[0].annotation_id).toBe(annotation.id);
+  });
+
+  test('Update after deletion', async () => {
+    const { bookId, apiKey, first, second, secondDeviceId, annotation, annotationId } = await setup();
+
+    const deleteResponse = await deleteAnnotation(bookId, annotationId, first);
+    expect(deleteResponse.status).toBe(204);
+
+    // Deleting twice is harmless
+    const secondDeleteResponse = await deleteAnnotation(bookId, annotationId, second);
+    expect(secondDeleteResponse.status).toBe(204);
+
+    const staleResponse = await updateAnnotation(bookId, annotationId, { ...annotation, note: 'Stale version' }, second);
+    expect(staleResponse.status).toBe(204);
+
+    const getAnnotationsResponse = await getAnnotations(bookId, first);
+    expect(getAnnotationsResponse.body.annotations).toHaveLength(0);
+
+    const activityResponse = await getDeviceActivity(secondDeviceId, apiKey);
+    expect(activityResponse.status).toBe(200);
+    expect(activityResponse.body).toHaveLength(1);
+    expect(activityResponse.body[0].event).toBe('DeletedAnnotationUpdate');
+  });
+
+  test('Activity of another user', async () => {
+    const { secondDeviceId } = await setup();
+    const { apiKey } = await setup();
+
+    const activityResponse = await getDeviceActivity(secondDeviceId, apiKey);
+    expect(activityResponse.status).toBe(404);
+    expect(activityResponse.body.message).toBe(DEVICE_NOT_FOUND);
+  });
+});
+
+describe('Export annotations', () => {
+  async function setup() {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const addAnnotationResponse = await addProsaAnnotation(uploadResponse.text, ALICE_NOTE, { jwt: registerResponse.body.jwt_token });
+    expect(addAnnotationResponse.status).toBe(200);
+
+    return { bookId: uploadResponse.text, apiKey: createApiKeyResponse.body.key };
+  }
+
+  test('Markdown', async () => {
+    const { bookId, apiKey } = await setup();
+
+    const exportResponse = await exportAnnotations(apiKey, undefined, bookId);
+    expect(exportResponse.status).toBe(200);
+    expect(exportResponse.headers['content-type']).toContain('text/markdown');
+    expect(exportResponse.text).toContain("# Alice's Adventures in Wonderland");
+    expect(exportResponse.text).toContain('*Lewis Carroll*');
+    expect(exportResponse.text).toContain(`## ${ALICE_NOTE.source}`);
+    expect(exportResponse.text).toContain(ALICE_NOTE.note);
+  });
+
+  test('JSON', async () => {
+    const { bookId, apiKey } = await setup();
+
+    const exportResponse = await exportAnnotations(apiKey, 'json', bookId);
+    expect(exportResponse.status).toBe(200);
+    expect(exportResponse.body).toHaveLength(1);
+    expect(exportResponse.body[0].book_id).toBe(bookId);
+    expect(exportResponse.body[0].title).toBe("Alice's Adventures in Wonderland");
+    expect(exportResponse.body[0].authors).toEqual(['Lewis Carroll']);
+    expect(exportResponse.body[0].annotations).toHaveLength(1);
+    expect(exportResponse.body[0].annotations[0].note).toBe(ALICE_NOTE.note);
+    expect(exportResponse.body[0].annotations[0].chapter).toBe(ALICE_NOTE.source);
+  });
+
+  test('CSV', async () => {
+    const { bookId, apiKey } = await setup();
+
+    const exportResponse = await exportAnnotations(apiKey, 'csv', bookId);
+    expect(exportResponse.status).toBe(200);
+    expect(exportResponse.headers['content-type']).toContain('text/csv');
+
+    const rows = exportResponse.text.trim().split('\r\n');
+    expect(rows).toHaveLength(2);
+    expect(rows[0]).toBe('Highlight,Title,Author,Note,Date');
+    expect(rows[1]).toContain("Alice's Adventures in Wonderland,Lewis Carroll,I loved this part!,");
+  });
+
+  test('Library', async () => {
+    const { bookId, apiKey } = await setup();
+    const { bookId: otherBookId } = await setup();
+
+    // Books are known to have annotations once they are fetched
+    let exportResponse = await exportAnnotations(apiKey, 'json', bookId);
+    expect(exportResponse.status).toBe(200);
+
+    exportResponse = await exportAnnotations(apiKey, 'json');
+    expect(exportResponse.status).toBe(200);
+
+    const bookIds = exportResponse.body.map((book: any) => book.book_id);
+    expect(bookIds).toContain(bookId);
+    expect(bookIds).not.toContain(otherBookId);
+  });
+
+  test('Invalid format', async () => {
+    const { bookId, apiKey } = await setup();
+
+    const exportResponse = await exportAnnotations(apiKey, 'pdf', bookId);
+    expect(exportResponse.status).toBe(400);
+  });
+
+  test('Invalid api key', async () => {
+    const { bookId, apiKey } = await setup();
+
+    const exportResponse = await exportAnnotations(apiKey, 'json', bookId);
+    expect(exportResponse.status).toBe(200);
+
+    // The key is checked against Prosa before any cached annotations are read
+    const invalidResponse = await exportAnnotations('invalid', 'json', bookId);
+    expect(invalidResponse.status).toBe(401);
+  });
+
+  test('Missing api key', async () => {
+    const exportResponse = await exportAnnotations();
+    expect(exportResponse.status).toBe(400);
+    expect(exportResponse.body.message).toBe(MISSING_API_KEY);
+  });
+});
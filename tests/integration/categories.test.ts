@@ -0,0 +1,127 @@
+import { DEVICE_NOT_LINKED, UNAUTHENTICATED, wait } from '../utils/common';
+import { ALICE_GENRES, CATEGORY_NOT_FOUND, getCategories, getCategory, getCategoryFeaturedLists, getCategoryProducts, syntheticCategoryId } from '../utils/kobont/categories';
+import { authDevice, linkDevice } from '../utils/kobont/devices';
+import { getMetadata } from '../utils/kobont/metadata';
+import { deleteBook as deleteProsaBook, uploadBook } from '../utils/prosa/books';
+import { createApiKey, registerUser } from '../utils/prosa/users';
+
+describe('Categories', () => {
+  test('Simple', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    // Wait for metadata to be extracted
+    await wait(1.0);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    // Categories are only known after the book's metadata has been translated
+    let categoriesResponse = await getCategories(authResponse.body.AccessToken);
+    expect(categoriesResponse.status).toBe(200);
+    expect(categoriesResponse.body).toEqual([]);
+
+    const getMetadataResponse = await getMetadata(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getMetadataResponse.status).toBe(200);
+
+    const expectedCategories = ALICE_GENRES.map((genre) => ({ Id: syntheticCategoryId(genre), Name: genre })).sort((a, b) => (a.Name < b.Name ? -1 : 1));
+
+    categoriesResponse = await getCategories(authResponse.body.AccessToken);
+    expect(categoriesResponse.status).toBe(200);
+    expect(categoriesResponse.body).toEqual(expectedCategories);
+
+    const categoryId = syntheticCategoryId(ALICE_GENRES[0]);
+
+    const categoryResponse = await getCategory(categoryId, authResponse.body.AccessToken);
+    expect(categoryResponse.status).toBe(200);
+    expect(categoryResponse.body).toEqual({ Id: categoryId, Name: ALICE_GENRES[0] });
+
+    const featuredResponse = await getCategoryFeaturedLists(categoryId, authResponse.body.AccessToken);
+    expect(featuredResponse.status).toBe(200);
+    expect(featuredResponse.body).toEqual([]);
+
+    const productsResponse = await getCategoryProducts(categoryId, authResponse.body.AccessToken);
+    expect(productsResponse.status).toBe(200);
+    expect(productsResponse.body.length).toBe(1);
+    expect(productsResponse.body[0].EntitlementId).toBe(uploadResponse.text);
+    expect(productsResponse.body[0].Genre).toBe(categoryId);
+  });
+
+  test('Deleted book', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    // Wait for metadata to be extracted
+    await wait(1.0);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const getMetadataResponse = await getMetadata(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getMetadataResponse.status).toBe(200);
+
+    const deleteResponse = await deleteProsaBook(uploadResponse.text, { jwt: registerResponse.body.jwt_token });
+    expect(deleteResponse.status).toBe(204);
+
+    const categoryId = syntheticCategoryId(ALICE_GENRES[0]);
+
+    const productsResponse = await getCategoryProducts(categoryId, authResponse.body.AccessToken);
+    expect(productsResponse.status).toBe(200);
+    expect(productsResponse.body).toEqual([]);
+
+    const categoryResponse = await getCategory(categoryId, authResponse.body.AccessToken);
+    expect(categoryResponse.status).toBe(404);
+    expect(categoryResponse.body.message).toBe(CATEGORY_NOT_FOUND);
+  });
+
+  test('Non existent category', async () => {
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, 'api-key');
+    expect(linkResponse.status).toBe(200);
+
+    const categoryResponse = await getCategory('non-existent', authResponse.body.AccessToken);
+    expect(categoryResponse.status).toBe(404);
+    expect(categoryResponse.body.message).toBe(CATEGORY_NOT_FOUND);
+
+    const productsResponse = await getCategoryProducts('non-existent', authResponse.body.AccessToken);
+    expect(productsResponse.status).toBe(404);
+    expect(productsResponse.body.message).toBe(CATEGORY_NOT_FOUND);
+  });
+
+  test('No auth', async () => {
+    const categoriesResponse = await getCategories();
+    expect(categoriesResponse.status).toBe(401);
+    expect(categoriesResponse.body.message).toBe(UNAUTHENTICATED);
+  });
+
+  test('Not linked', async () => {
+    const { response: authResponse } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const categoriesResponse = await getCategories(authResponse.body.AccessToken);
+    expect(categoriesResponse.status).toBe(401);
+    expect(categoriesResponse.body.message).toBe(DEVICE_NOT_LINKED);
+  });
+});
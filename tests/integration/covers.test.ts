@@ -1,8 +1,10 @@
 import sizeOf from 'image-size';
 import { Buffer } from 'node:buffer';
-import { getCover, INVALID_COVER_TOKEN } from '../utils/kobont/covers';
+import { COVER_EXPIRATION, wait } from '../utils/common';
+import { getCover, getTemplatedCover, INVALID_COVER_TOKEN } from '../utils/kobont/covers';
 import { authDevice, linkDevice, unlinkDevice } from '../utils/kobont/devices';
 import { getMetadata } from '../utils/kobont/metadata';
+import { sync } from '../utils/kobont/sync';
 import { deleteBook as deleteProsaBook, uploadBook } from '../utils/prosa/books';
 import { deleteCover as deleteProsaCover, getCover as getProsaCover } from '../utils/prosa/covers';
 import { createApiKey, registerUser } from '../utils/prosa/users';
@@ -28,14 +30,23 @@ describe('Fetch image', () => {
     const getMetadataResponse = await getMetadata(uploadResponse.text, authResponse.body.AccessToken);
     expect(getMetadataResponse.status).toBe(200);
 
-    const token = getMetadataResponse.body[0].CoverImageId.split('?token=')[1];
+    const token = getMetadataResponse.body[0].CoverImageId.split('/')[1];
     const downloadCoverResponse = await getCover(uploadResponse.text, undefined, undefined, token);
     expect(downloadCoverResponse.status).toBe(200);
 
+    expect(downloadCoverResponse.headers['content-type']).toBe('image/jpeg');
+    expect(downloadCoverResponse.headers['etag']).toBeDefined();
+    expect(downloadCoverResponse.headers['cache-control']).toContain('max-age=');
+
     const expectedResponse = await getProsaCover(uploadResponse.text, { jwt: registerResponse.body.jwt_token });
     expect(expectedResponse.status).toBe(200);
 
-    expect(downloadCoverResponse.body).toEqual(expectedResponse.body);
+    const cover = sizeOf(Buffer.from(await downloadCoverResponse.body));
+    const expectedCover = sizeOf(Buffer.from(await expectedResponse.body));
+
+    expect(cover.type).toBe('jpg');
+    expect(cover.width).toBe(expectedCover.width);
+    expect(cover.height).toBe(expectedCover.height);
   });
 
   test('Resize', async () => {
@@ -58,15 +69,123 @@ describe('Fetch image', () => {
     const getMetadataResponse = await getMetadata(uploadResponse.text, authResponse.body.AccessToken);
     expect(getMetadataResponse.status).toBe(200);
 
-    const token = getMetadataResponse.body[0].CoverImageId.split('?token=')[1];
+    const token = getMetadataResponse.body[0].CoverImageId.split('/')[1];
     const downloadCoverResponse = await getCover(uploadResponse.text, 200, 300, token);
     expect(downloadCoverResponse.status).toBe(200);
 
     const buffer = Buffer.from(await downloadCoverResponse.body);
     const { width, height } = sizeOf(buffer);
 
-    expect(height).toBe(200);
-    expect(width).toBe(300);
+    expect(height).toBeLessThanOrEqual(200);
+    expect(width).toBeLessThanOrEqual(300);
+    expect(height === 200 || width === 300).toBe(true);
+  });
+
+  test('Resize with image template', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const getMetadataResponse = await getMetadata(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getMetadataResponse.status).toBe(200);
+
+    const imageId = getMetadataResponse.body[0].CoverImageId;
+    let downloadCoverResponse = await getTemplatedCover(imageId, 150, 400, 60, true);
+    expect(downloadCoverResponse.status).toBe(200);
+
+    let { width, height } = sizeOf(Buffer.from(await downloadCoverResponse.body));
+    expect(width).toBeLessThanOrEqual(150);
+    expect(height).toBeLessThanOrEqual(400);
+    expect(width === 150 || height === 400).toBe(true);
+
+    downloadCoverResponse = await getTemplatedCover(imageId, 150, 400);
+    expect(downloadCoverResponse.status).toBe(200);
+
+    ({ width, height } = sizeOf(Buffer.from(await downloadCoverResponse.body)));
+    expect(width).toBeLessThanOrEqual(150);
+    expect(height).toBeLessThanOrEqual(400);
+  });
+
+  test('Resize is not upscaled', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const getMetadataResponse = await getMetadata(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getMetadataResponse.status).toBe(200);
+
+    const imageId = getMetadataResponse.body[0].CoverImageId;
+    const downloadCoverResponse = await getTemplatedCover(imageId, 20000, 20000);
+    expect(downloadCoverResponse.status).toBe(200);
+
+    const expectedResponse = await getProsaCover(uploadResponse.text, { jwt: registerResponse.body.jwt_token });
+    expect(expectedResponse.status).toBe(200);
+
+    const cover = sizeOf(Buffer.from(await downloadCoverResponse.body));
+    const expectedCover = sizeOf(Buffer.from(await expectedResponse.body));
+
+    expect(cover.width).toBe(expectedCover.width);
+    expect(cover.height).toBe(expectedCover.height);
+  });
+
+  test('Cached thumbnail', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const getMetadataResponse = await getMetadata(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getMetadataResponse.status).toBe(200);
+
+    const imageId = getMetadataResponse.body[0].CoverImageId;
+    const firstResponse = await getTemplatedCover(imageId, 150, 400);
+    expect(firstResponse.status).toBe(200);
+    expect(firstResponse.headers['content-type']).toBe('image/jpeg');
+    expect(firstResponse.headers['etag']).toBeDefined();
+    expect(firstResponse.headers['cache-control']).toContain('max-age=');
+
+    const secondResponse = await getTemplatedCover(imageId, 150, 400);
+    expect(secondResponse.status).toBe(200);
+    expect(secondResponse.headers['etag']).toBe(firstResponse.headers['etag']);
+    expect(secondResponse.body).toEqual(firstResponse.body);
+
+    const notModifiedResponse = await getTemplatedCover(imageId, 150, 400, undefined, false, firstResponse.headers['etag']);
+    expect(notModifiedResponse.status).toBe(304);
   });
 
   test('Non-existent book', async () => {
@@ -92,12 +211,12 @@ describe('Fetch image', () => {
     const deleteBookResponse = await deleteProsaBook(uploadResponse.text, { apiKey: createApiKeyResponse.body.key });
     expect(deleteBookResponse.status).toBe(204);
 
-    const token = getMetadataResponse.body[0].CoverImageId.split('?token=')[1];
+    const token = getMetadataResponse.body[0].CoverImageId.split('/')[1];
     const downloadBookResponse = await getCover(uploadResponse.text, undefined, undefined, token);
     expect(downloadBookResponse.status).toBe(404);
   });
 
-  test('Non-existent cover', async () => {
+  test('Placeholder for non-existent cover', async () => {
     const { response: registerResponse } = await registerUser();
     expect(registerResponse.status).toBe(200);
     const userId = registerResponse.body.user_id;
@@ -120,9 +239,65 @@ describe('Fetch image', () => {
     const deleteCoverResponse = await deleteProsaCover(uploadResponse.text, { apiKey: createApiKeyResponse.body.key });
     expect(deleteCoverResponse.status).toBe(204);
 
-    const token = getMetadataResponse.body[0].CoverImageId.split('?token=')[1];
-    const downloadBookResponse = await getCover(uploadResponse.text, undefined, undefined, token);
-    expect(downloadBookResponse.status).toBe(404);
+    const imageId = getMetadataResponse.body[0].CoverImageId;
+    const token = imageId.split('/')[1];
+    let downloadCoverResponse = await getCover(uploadResponse.text, undefined, undefined, token);
+    expect(downloadCoverResponse.status).toBe(200);
+
+    let { width, height, type } = sizeOf(Buffer.from(await downloadCoverResponse.body));
+    expect(type).toBe('jpg');
+    expect(width).toBe(600);
+    expect(height).toBe(800);
+
+    downloadCoverResponse = await getTemplatedCover(imageId, 150, 220);
+    expect(downloadCoverResponse.status).toBe(200);
+
+    ({ width, height, type } = sizeOf(Buffer.from(await downloadCoverResponse.body)));
+    expect(type).toBe('jpg');
+    expect(width).toBe(150);
+    expect(height).toBe(220);
+
+    const secondResponse = await getTemplatedCover(imageId, 150, 220);
+    expect(secondResponse.body).toEqual(downloadCoverResponse.body);
+  });
+
+  test('Placeholder with out of range size', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read', 'Delete'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const getMetadataResponse = await getMetadata(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getMetadataResponse.status).toBe(200);
+
+    const deleteCoverResponse = await deleteProsaCover(uploadResponse.text, { apiKey: createApiKeyResponse.body.key });
+    expect(deleteCoverResponse.status).toBe(204);
+
+    const imageId = getMetadataResponse.body[0].CoverImageId;
+    let downloadCoverResponse = await getTemplatedCover(imageId, 100, 70000);
+    expect(downloadCoverResponse.status).toBe(200);
+
+    let { width, height } = sizeOf(Buffer.from(await downloadCoverResponse.body));
+    expect(width).toBe(100);
+    expect(height).toBe(4096);
+
+    downloadCoverResponse = await getTemplatedCover(imageId, 0, 0);
+    expect(downloadCoverResponse.status).toBe(200);
+
+    ({ width, height } = sizeOf(Buffer.from(await downloadCoverResponse.body)));
+    expect(width).toBe(1);
+    expect(height).toBe(1);
   });
 
   test('Incorrect token', async () => {
@@ -148,7 +323,7 @@ describe('Fetch image', () => {
     const getMetadataResponse = await getMetadata(uploadResponse.text, authResponse.body.AccessToken);
     expect(getMetadataResponse.status).toBe(200);
 
-    const token = getMetadataResponse.body[0].CoverImageId.split('?token=')[1];
+    const token = getMetadataResponse.body[0].CoverImageId.split('/')[1];
     let downloadCoverResponse = await getCover(uploadResponse.text, undefined, undefined, 'incorrect');
     expect(downloadCoverResponse.status).toBe(403);
     expect(downloadCoverResponse.body.message).toBe(INVALID_COVER_TOKEN);
@@ -195,8 +370,113 @@ describe('Fetch image', () => {
     linkResponse = await linkDevice(deviceId, createApiKeyResponse2.body.key);
     expect(linkResponse.status).toBe(200);
 
-    const token = getMetadataResponse.body[0].CoverImageId.split('?token=')[1];
+    const token = getMetadataResponse.body[0].CoverImageId.split('/')[1];
     const downloadCoverResponse = await getCover(uploadResponse.text, undefined, undefined, token);
     expect(downloadCoverResponse.status).toBe(403);
   });
 });
+
+describe('Cover tokens', () => {
+  test('Expired token', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const getMetadataResponse = await getMetadata(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getMetadataResponse.status).toBe(200);
+
+    const token = getMetadataResponse.body[0].CoverImageId.split('/')[1];
+    let downloadCoverResponse = await getCover(uploadResponse.text, undefined, undefined, token);
+    expect(downloadCoverResponse.status).toBe(200);
+
+    await wait(COVER_EXPIRATION + 1);
+
+    downloadCoverResponse = await getCover(uploadResponse.text, undefined, undefined, token);
+    expect(downloadCoverResponse.status).toBe(403);
+    expect(downloadCoverResponse.body.message).toBe(INVALID_COVER_TOKEN);
+  }, (COVER_EXPIRATION + 10) * 1000);
+
+  test('Rotated on sync', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    let syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(1);
+    const oldImageId = syncResponse.body[0].NewEntitlement.BookMetadata.CoverImageId;
+
+    // Tokens are rotated by the first sync past half of their lifetime
+    await wait(COVER_EXPIRATION / 2 + 1);
+
+    syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(1);
+    const newImageId = syncResponse.body[0].NewEntitlement.BookMetadata.CoverImageId;
+    expect(newImageId).not.toEqual(oldImageId);
+
+    let downloadCoverResponse = await getCover(uploadResponse.text, undefined, undefined, oldImageId.split('/')[1]);
+    expect(downloadCoverResponse.status).toBe(403);
+    expect(downloadCoverResponse.body.message).toBe(INVALID_COVER_TOKEN);
+
+    downloadCoverResponse = await getCover(uploadResponse.text, undefined, undefined, newImageId.split('/')[1]);
+    expect(downloadCoverResponse.status).toBe(200);
+  }, (COVER_EXPIRATION + 10) * 1000);
+
+  test('Revoked on unlink', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    let linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const getMetadataResponse = await getMetadata(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getMetadataResponse.status).toBe(200);
+
+    const unlinkResponse = await unlinkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(unlinkResponse.status).toBe(200);
+
+    // Linking the device again doesn't bring back its old tokens
+    linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const token = getMetadataResponse.body[0].CoverImageId.split('/')[1];
+    const downloadCoverResponse = await getCover(uploadResponse.text, undefined, undefined, token);
+    expect(downloadCoverResponse.status).toBe(403);
+    expect(downloadCoverResponse.body.message).toBe(INVALID_COVER_TOKEN);
+  });
+});
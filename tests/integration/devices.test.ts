@@ -1,5 +1,5 @@
 import { INVALID_TOKEN, randomString } from '../utils/common';
-import { authDevice, authRefreshDevice, DEVICE_ALREADY_LINKED, DEVICE_ALREADY_UNLINKED, DEVICE_NOT_FOUND, getLinkedDevices, getUnlinkedDevices, INVALID_API_KEY, linkDevice, MISSING_API_KEY, unlinkDevice } from '../utils/kobont/devices';
+import { authDevice, authRefreshDevice, DEVICE_ALREADY_LINKED, DEVICE_ALREADY_UNLINKED, DEVICE_NOT_FOUND, getLinkedDevices, getSmartShelfRules, getUnlinkedDevices, INVALID_API_KEY, linkDevice, MISSING_API_KEY, setSmartShelfRules, unlinkDevice } from '../utils/kobont/devices';
 
 describe('Device auth', () => {
   test('Simple', async () => {
@@ -115,3 +115,93 @@ describe('Device linking', () => {
     expect(unlinkResponse.body.message).toBe(INVALID_API_KEY);
   });
 });
+
+describe('Smart shelf rules', () => {
+  test('Simple', async () => {
+    const apiKey = randomString(16);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, apiKey);
+    expect(linkResponse.status).toBe(200);
+
+    let getRulesResponse = await getSmartShelfRules(deviceId, apiKey);
+    expect(getRulesResponse.status).toBe(200);
+    expect(getRulesResponse.body).toEqual({ rules: [] });
+
+    const setRulesResponse = await setSmartShelfRules(deviceId, ['currently_reading', 'series'], apiKey);
+    expect(setRulesResponse.status).toBe(204);
+
+    getRulesResponse = await getSmartShelfRules(deviceId, apiKey);
+    expect(getRulesResponse.status).toBe(200);
+    expect(getRulesResponse.body).toEqual({ rules: ['currently_reading', 'series'] });
+  });
+
+  test('Rules are reset on unlink', async () => {
+    const apiKey = randomString(16);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    let linkResponse = await linkDevice(deviceId, apiKey);
+    expect(linkResponse.status).toBe(200);
+
+    const setRulesResponse = await setSmartShelfRules(deviceId, ['unread'], apiKey);
+    expect(setRulesResponse.status).toBe(204);
+
+    const unlinkResponse = await unlinkDevice(deviceId, apiKey);
+    expect(unlinkResponse.status).toBe(200);
+
+    linkResponse = await linkDevice(deviceId, apiKey);
+    expect(linkResponse.status).toBe(200);
+
+    const getRulesResponse = await getSmartShelfRules(deviceId, apiKey);
+    expect(getRulesResponse.status).toBe(200);
+    expect(getRulesResponse.body).toEqual({ rules: [] });
+  });
+
+  test('Unknown rule', async () => {
+    const apiKey = randomString(16);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, apiKey);
+    expect(linkResponse.status).toBe(200);
+
+    const setRulesResponse = await setSmartShelfRules(deviceId, ['favorites'], apiKey);
+    expect(setRulesResponse.status).toBe(422);
+  });
+
+  test('Wrong api key', async () => {
+    const apiKey = randomString(16);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, apiKey);
+    expect(linkResponse.status).toBe(200);
+
+    const getRulesResponse = await getSmartShelfRules(deviceId, randomString(16));
+    expect(getRulesResponse.status).toBe(404);
+    expect(getRulesResponse.body.message).toBe(DEVICE_NOT_FOUND);
+
+    const setRulesResponse = await setSmartShelfRules(deviceId, ['unread'], randomString(16));
+    expect(setRulesResponse.status).toBe(404);
+    expect(setRulesResponse.body.message).toBe(DEVICE_NOT_FOUND);
+  });
+
+  test('Missing api key', async () => {
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const getRulesResponse = await getSmartShelfRules(deviceId);
+    expect(getRulesResponse.status).toBe(400);
+    expect(getRulesResponse.body.message).toBe(MISSING_API_KEY);
+
+    const setRulesResponse = await setSmartShelfRules(deviceId, ['unread']);
+    expect(setRulesResponse.status).toBe(400);
+    expect(setRulesResponse.body.message).toBe(MISSING_API_KEY);
+  });
+});
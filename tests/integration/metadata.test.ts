@@ -2,7 +2,7 @@ import { DEVICE_NOT_LINKED, UNAUTHENTICATED, wait } from '../utils/common';
 import { authDevice, linkDevice } from '../utils/kobont/devices';
 import { generateAliceMetadata, generateDefaultMetadata, getMetadata, normalizeMetadata } from '../utils/kobont/metadata';
 import { uploadBook } from '../utils/prosa/books';
-import { deleteMetadata } from '../utils/prosa/metadata';
+import { deleteMetadata, updateMetadata } from '../utils/prosa/metadata';
 import { createApiKey, registerUser } from '../utils/prosa/users';
 
 describe('Metadata', () => {
@@ -84,6 +84,238 @@ describe('Metadata', () => {
     expect(normalizeMetadata(getMetadataResponse.body[0])).toEqual(expectedResponse[0]);
   });
 
+  test('Contributor roles', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    // Wait for metadata to be extracted
+    await wait(1.0);
+
+    const metadata = {
+      title: "Alice's Adventures in Wonderland",
+      contributors: [
+        { name: 'Lewis Carroll', role: 'Author' },
+        { name: 'John Tenniel', role: 'Illustrator' },
+        { name: 'Jane Doe', role: 'Translator' },
+        { name: 'John Doe', role: 'Editor' },
+        { name: 'Some Reader', role: 'Narrator' },
+        { name: 'Someone Else', role: 'Unknown' }
+      ]
+    };
+
+    const updateMetadataResponse = await updateMetadata(uploadResponse.text, metadata, { jwt: registerResponse.body.jwt_token });
+    expect(updateMetadataResponse.status).toBe(204);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const getMetadataResponse = await getMetadata(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getMetadataResponse.status).toBe(200);
+    expect(getMetadataResponse.body[0].Contributors).toEqual(['Lewis Carroll']);
+    expect(getMetadataResponse.body[0].ContributorRoles).toEqual([
+      { Name: 'Lewis Carroll', Role: 'Author' },
+      { Name: 'John Tenniel', Role: 'Illustrator' },
+      { Name: 'Jane Doe', Role: 'Translator' },
+      { Name: 'John Doe', Role: 'Editor' },
+      { Name: 'Some Reader', Role: 'Narrator' }
+    ]);
+  });
+
+  test('Series', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const aliceResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(aliceResponse.status).toBe(200);
+
+    const ozResponse = await uploadBook(userId, 'The_Wonderful_Wizard_of_Oz.epub', { jwt: registerResponse.body.jwt_token });
+    expect(ozResponse.status).toBe(200);
+
+    const gatsbyResponse = await uploadBook(userId, 'The_Great_Gatsby.epub', { jwt: registerResponse.body.jwt_token });
+    expect(gatsbyResponse.status).toBe(200);
+
+    // Wait for metadata to be extracted
+    await wait(1.0);
+
+    const series = [
+      { bookId: aliceResponse.text, author: 'Lewis Carroll', number: 2 },
+      { bookId: ozResponse.text, author: 'Lewis Carroll', number: 1.5 },
+      { bookId: gatsbyResponse.text, author: 'F. Scott Fitzgerald', number: 1 }
+    ];
+
+    for (const { bookId, author, number } of series) {
+      const metadata = {
+        contributors: [{ name: author, role: 'Author' }],
+        series: { title: 'Classics', number: number }
+      };
+
+      const updateMetadataResponse = await updateMetadata(bookId, metadata, { jwt: registerResponse.body.jwt_token });
+      expect(updateMetadataResponse.status).toBe(204);
+    }
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const responses = [];
+    for (const { bookId } of series) {
+      const getMetadataResponse = await getMetadata(bookId, authResponse.body.AccessToken);
+      expect(getMetadataResponse.status).toBe(200);
+      responses.push(getMetadataResponse.body[0].Series);
+    }
+
+    const [alice, oz, gatsby] = responses;
+
+    expect(alice.Name).toBe('Classics');
+    expect(alice.Number).toBe('2');
+    expect(alice.NumberFloat).toBe(2);
+    expect(oz.Number).toBe('1.5');
+    expect(oz.NumberFloat).toBe(1.5);
+
+    // Series with the same name are only grouped together when they share an author
+    expect(alice.Id).toMatch(/^[0-9a-f]{8}-[0-9a-f]{4}-4[0-9a-f]{3}-[89ab][0-9a-f]{3}-[0-9a-f]{12}$/);
+    expect(oz.Id).toBe(alice.Id);
+    expect(gatsby.Id).not.toBe(alice.Id);
+
+    // Series IDs are stable across requests
+    const getMetadataResponse = await getMetadata(aliceResponse.text, authResponse.body.AccessToken);
+    expect(getMetadataResponse.status).toBe(200);
+    expect(getMetadataResponse.body[0].Series.Id).toBe(alice.Id);
+  });
+
+  test('ISBN', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const validResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(validResponse.status).toBe(200);
+
+    const invalidResponse = await uploadBook(userId, 'The_Great_Gatsby.epub', { jwt: registerResponse.body.jwt_token });
+    expect(invalidResponse.status).toBe(200);
+
+    // Wait for metadata to be extracted
+    await wait(1.0);
+
+    let updateMetadataResponse = await updateMetadata(validResponse.text, { isbn: '0-306-40615-2' }, { jwt: registerResponse.body.jwt_token });
+    expect(updateMetadataResponse.status).toBe(204);
+
+    updateMetadataResponse = await updateMetadata(invalidResponse.text, { isbn: '978-0-306-40615-8' }, { jwt: registerResponse.body.jwt_token });
+    expect(updateMetadataResponse.status).toBe(204);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    let getMetadataResponse = await getMetadata(validResponse.text, authResponse.body.AccessToken);
+    expect(getMetadataResponse.status).toBe(200);
+    expect(getMetadataResponse.body[0].Isbn).toBe('9780306406157');
+    expect(getMetadataResponse.body[0].ExternalIds).toEqual(['isbn:9780306406157']);
+
+    getMetadataResponse = await getMetadata(invalidResponse.text, authResponse.body.AccessToken);
+    expect(getMetadataResponse.status).toBe(200);
+    expect(getMetadataResponse.body[0].Isbn).toBe(null);
+    expect(getMetadataResponse.body[0].ExternalIds).toEqual([]);
+  });
+
+  test('Language tags', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    // Wait for metadata to be extracted
+    await wait(1.0);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const cases = [
+      { language: 'pt-BR', locale: { LanguageCode: 'pt', ScriptCode: '', CountryCode: 'BR' } },
+      { language: 'zh-Hant-TW', locale: { LanguageCode: 'zh', ScriptCode: 'Hant', CountryCode: 'TW' } },
+      { language: 'ger', locale: { LanguageCode: 'de', ScriptCode: '', CountryCode: '' } },
+      { language: 'fre', locale: { LanguageCode: 'fr', ScriptCode: '', CountryCode: '' } }
+    ];
+
+    for (const { language, locale } of cases) {
+      const updateMetadataResponse = await updateMetadata(uploadResponse.text, { language }, { jwt: registerResponse.body.jwt_token });
+      expect(updateMetadataResponse.status).toBe(204);
+
+      const getMetadataResponse = await getMetadata(uploadResponse.text, authResponse.body.AccessToken);
+      expect(getMetadataResponse.status).toBe(200);
+      expect(getMetadataResponse.body[0].Locale).toEqual(locale);
+    }
+  });
+
+  test('Description sanitization', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const htmlResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(htmlResponse.status).toBe(200);
+
+    const markdownResponse = await uploadBook(userId, 'The_Great_Gatsby.epub', { jwt: registerResponse.body.jwt_token });
+    expect(markdownResponse.status).toBe(200);
+
+    // Wait for metadata to be extracted
+    await wait(1.0);
+
+    const html = '<p style="color: red">Down the <b>rabbit hole</b><script>alert(1)</script><img src="http://tracker/pixel.gif"></p><div>The end</div>';
+    let updateMetadataResponse = await updateMetadata(htmlResponse.text, { description: html }, { jwt: registerResponse.body.jwt_token });
+    expect(updateMetadataResponse.status).toBe(204);
+
+    const markdown = 'A **classic** novel\nof the Jazz Age\n\nBy *F. Scott Fitzgerald*';
+    updateMetadataResponse = await updateMetadata(markdownResponse.text, { description: markdown }, { jwt: registerResponse.body.jwt_token });
+    expect(updateMetadataResponse.status).toBe(204);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    let getMetadataResponse = await getMetadata(htmlResponse.text, authResponse.body.AccessToken);
+    expect(getMetadataResponse.status).toBe(200);
+    expect(getMetadataResponse.body[0].Description).toBe('<p>Down the <b>rabbit hole</b></p>The end');
+
+    getMetadataResponse = await getMetadata(markdownResponse.text, authResponse.body.AccessToken);
+    expect(getMetadataResponse.status).toBe(200);
+    expect(getMetadataResponse.body[0].Description).toBe('<p>A <strong>classic</strong> novel<br>\nof the Jazz Age</p>\n<p>By <em>F. Scott Fitzgerald</em></p>');
+  });
+
   test('Wrong capabilities', async () => {
     const { response: registerResponse } = await registerUser();
     expect(registerResponse.status).toBe(200);
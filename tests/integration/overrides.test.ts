@@ -0,0 +1,236 @@
+import { wait } from '../utils/common';
+import { authDevice, linkDevice, MISSING_API_KEY } from '../utils/kobont/devices';
+import { getMetadata } from '../utils/kobont/metadata';
+import { deleteOverride, getOverride, OVERRIDE_NOT_FOUND, setOverride } from '../utils/kobont/overrides';
+import { sync } from '../utils/kobont/sync';
+import { uploadBook } from '../utils/prosa/books';
+import { createApiKey, registerUser } from '../utils/prosa/users';
+
+const OVERRIDE = {
+  title: 'Alice',
+  subtitle: 'The Annotated Edition',
+  series: { title: 'Wonderland', number: 1 },
+  sort_author: 'Carroll, Lewis',
+  description: 'Down the rabbit hole.'
+};
+
+describe('Metadata overrides', () => {
+  test('Simple', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    // Wait for metadata to be extracted
+    await wait(1.0);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+    const apiKey = createApiKeyResponse.body.key;
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, apiKey);
+    expect(linkResponse.status).toBe(200);
+
+    const setOverrideResponse = await setOverride(uploadResponse.text, OVERRIDE, apiKey);
+    expect(setOverrideResponse.status).toBe(204);
+
+    const getOverrideResponse = await getOverride(uploadResponse.text, apiKey);
+    expect(getOverrideResponse.status).toBe(200);
+    expect(getOverrideResponse.body).toEqual(OVERRIDE);
+
+    const getMetadataResponse = await getMetadata(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getMetadataResponse.status).toBe(200);
+
+    const metadata = getMetadataResponse.body[0];
+    expect(metadata.Title).toBe('Alice');
+    expect(metadata.Subtitle).toBe('The Annotated Edition');
+    expect(metadata.Description).toBe('Down the rabbit hole.');
+    expect(metadata.Contributors).toEqual(['Carroll, Lewis']);
+    expect(metadata.Series.Name).toBe('Wonderland');
+    expect(metadata.Series.Number).toBe('1');
+  });
+
+  test('Partial override', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    // Wait for metadata to be extracted
+    await wait(1.0);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+    const apiKey = createApiKeyResponse.body.key;
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, apiKey);
+    expect(linkResponse.status).toBe(200);
+
+    const setOverrideResponse = await setOverride(uploadResponse.text, { title: 'Alice' }, apiKey);
+    expect(setOverrideResponse.status).toBe(204);
+
+    const getMetadataResponse = await getMetadata(uploadResponse.text, authResponse.body.AccessToken);
+    expect(getMetadataResponse.status).toBe(200);
+    expect(getMetadataResponse.body[0].Title).toBe('Alice');
+    expect(getMetadataResponse.body[0].Contributors).toEqual(['Lewis Carroll']);
+    expect(getMetadataResponse.body[0].Series).toBe(null);
+  });
+
+  test('Sync after override', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    // Wait for cover and metadata to be extracted
+    await wait(1.0);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+    const apiKey = createApiKeyResponse.body.key;
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, apiKey);
+    expect(linkResponse.status).toBe(200);
+
+    let syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(1);
+
+    syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(0);
+
+    const setOverrideResponse = await setOverride(uploadResponse.text, { title: 'Alice' }, apiKey);
+    expect(setOverrideResponse.status).toBe(204);
+
+    syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(1);
+    expect(syncResponse.body[0].NewEntitlement.BookEntitlement.Id).toEqual(uploadResponse.text);
+    expect(syncResponse.body[0].NewEntitlement.BookMetadata.Title).toEqual('Alice');
+
+    syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(0);
+
+    const deleteOverrideResponse = await deleteOverride(uploadResponse.text, apiKey);
+    expect(deleteOverrideResponse.status).toBe(204);
+
+    syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(1);
+    expect(syncResponse.body[0].NewEntitlement.BookMetadata.Title).toEqual("Alice's Adventures in Wonderland");
+  });
+
+  test('Delete override', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+    const apiKey = createApiKeyResponse.body.key;
+
+    const setOverrideResponse = await setOverride(uploadResponse.text, OVERRIDE, apiKey);
+    expect(setOverrideResponse.status).toBe(204);
+
+    let deleteOverrideResponse = await deleteOverride(uploadResponse.text, apiKey);
+    expect(deleteOverrideResponse.status).toBe(204);
+
+    const getOverrideResponse = await getOverride(uploadResponse.text, apiKey);
+    expect(getOverrideResponse.status).toBe(404);
+    expect(getOverrideResponse.body.message).toBe(OVERRIDE_NOT_FOUND);
+
+    deleteOverrideResponse = await deleteOverride(uploadResponse.text, apiKey);
+    expect(deleteOverrideResponse.status).toBe(404);
+    expect(deleteOverrideResponse.body.message).toBe(OVERRIDE_NOT_FOUND);
+  });
+
+  test('Overrides are per api key', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const otherApiKeyResponse = await createApiKey(userId, 'Other Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(otherApiKeyResponse.status).toBe(200);
+
+    const setOverrideResponse = await setOverride(uploadResponse.text, OVERRIDE, createApiKeyResponse.body.key);
+    expect(setOverrideResponse.status).toBe(204);
+
+    const getOverrideResponse = await getOverride(uploadResponse.text, otherApiKeyResponse.body.key);
+    expect(getOverrideResponse.status).toBe(404);
+    expect(getOverrideResponse.body.message).toBe(OVERRIDE_NOT_FOUND);
+  });
+
+  test('Non existent book', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const setOverrideResponse = await setOverride('non-existent', OVERRIDE, createApiKeyResponse.body.key);
+    expect(setOverrideResponse.status).toBe(404);
+
+    const getOverrideResponse = await getOverride('non-existent', createApiKeyResponse.body.key);
+    expect(getOverrideResponse.status).toBe(404);
+
+    const deleteOverrideResponse = await deleteOverride('non-existent', createApiKeyResponse.body.key);
+    expect(deleteOverrideResponse.status).toBe(404);
+  });
+
+  test('Invalid api key', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadResponse.status).toBe(200);
+
+    // The key is checked against Prosa before the stored override is read
+    const getOverrideResponse = await getOverride(uploadResponse.text, 'invalid');
+    expect(getOverrideResponse.status).toBe(401);
+
+    const deleteOverrideResponse = await deleteOverride(uploadResponse.text, 'invalid');
+    expect(deleteOverrideResponse.status).toBe(401);
+  });
+
+  test('Missing api key', async () => {
+    const setOverrideResponse = await setOverride('non-existent', OVERRIDE);
+    expect(setOverrideResponse.status).toBe(400);
+    expect(setOverrideResponse.body.message).toBe(MISSING_API_KEY);
+
+    const getOverrideResponse = await getOverride('non-existent');
+    expect(getOverrideResponse.status).toBe(400);
+    expect(getOverrideResponse.body.message).toBe(MISSING_API_KEY);
+
+    const deleteOverrideResponse = await deleteOverride('non-existent');
+    expect(deleteOverrideResponse.status).toBe(400);
+    expect(deleteOverrideResponse.body.message).toBe(MISSING_API_KEY);
+  });
+});
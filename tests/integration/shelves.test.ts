@@ -1,6 +1,6 @@
 import { DEVICE_NOT_LINKED, UNAUTHENTICATED } from '../utils/common';
 import { authDevice, linkDevice, unlinkDevice } from '../utils/kobont/devices';
-import { addBooksToShelf, createShelf, deleteBooksFromShelf, deleteShelf, renameShelf } from '../utils/kobont/shelves';
+import { addBooksToShelf, addItemsToShelf, createShelf, deleteBooksFromShelf, deleteShelf, renameShelf } from '../utils/kobont/shelves';
 import { uploadBook } from '../utils/prosa/books';
 import { getShelfMetadata as getProsaShelfMetadata, listBooksFromShelf } from '../utils/prosa/shelves';
 import { createApiKey, registerUser } from '../utils/prosa/users';
@@ -268,6 +268,39 @@ describe('Add book to shelf', () => {
     expect(listShelfBooksResponse.body).toEqual([uploadBookResponse.text]);
   });
 
+  test('Unsupported item type', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadBookResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadBookResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Create', 'Update'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const createShelfResponse = await createShelf('new-shelf', authResponse.body.AccessToken);
+    expect(createShelfResponse.status).toBe(201);
+
+    const items = [
+      { Type: 'SomeFutureTagItem', Id: 'unknown' },
+      { Type: 'ProductRevisionTagItem', RevisionId: uploadBookResponse.text }
+    ];
+    const addItemsToShelfResponse = await addItemsToShelf(createShelfResponse.text, items, authResponse.body.AccessToken);
+    expect(addItemsToShelfResponse.status).toBe(201);
+    expect(addItemsToShelfResponse.body).toEqual([uploadBookResponse.text]);
+
+    const listShelfBooksResponse = await listBooksFromShelf(createShelfResponse.text, { jwt: registerResponse.body.jwt_token });
+    expect(listShelfBooksResponse.status).toBe(200);
+    expect(listShelfBooksResponse.body).toEqual([uploadBookResponse.text]);
+  });
+
   test('Wrong capabilities', async () => {
     const { response: registerResponse } = await registerUser();
     expect(registerResponse.status).toBe(200);
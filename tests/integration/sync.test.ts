@@ -1,9 +1,11 @@
-import { DEVICE_NOT_LINKED, UNAUTHENTICATED, wait } from '../utils/common';
-import { authDevice, linkDevice } from '../utils/kobont/devices';
-import { addBooksToShelf } from '../utils/kobont/shelves';
+import { ADMIN_KEY, DEVICE_NOT_LINKED, UNAUTHENTICATED, wait } from '../utils/common';
+import { authDevice, getAcceptedShelves, getSharedShelves, linkDevice, setAcceptedShelves, setSharedShelves, setSmartShelfRules, SHELF_SHARE_NOT_FOUND, unlinkDevice } from '../utils/kobont/devices';
+import { addBooksToShelf, createShelf as createKoboShelf, deleteShelf as deleteKoboShelf, READ_ONLY_SHELF, renameShelf, SHARED_SHELF } from '../utils/kobont/shelves';
+import { updateState } from '../utils/kobont/state';
 import { sync } from '../utils/kobont/sync';
 import { deleteBook, uploadBook } from '../utils/prosa/books';
-import { createShelf, deleteBookFromShelf, deleteShelf, updateShelf } from '../utils/prosa/shelves';
+import { updateMetadata } from '../utils/prosa/metadata';
+import { addBookToShelf, createShelf, deleteBookFromShelf, deleteShelf, updateShelf } from '../utils/prosa/shelves';
 import { createApiKey, registerUser } from '../utils/prosa/users';
 
 describe('Book syncing', () => {
@@ -131,10 +133,12 @@ describe('Shelf syncing', () => {
     syncResponse = await sync(undefined, authResponse.body.AccessToken);
     expect(syncResponse.status).toBe(200);
     expect(syncResponse.body).toHaveLength(1);
-    expect(syncResponse.body[0]).toHaveProperty('NewTag');
-    expect(syncResponse.body[0].NewTag).toHaveProperty('Tag');
-    expect(syncResponse.body[0].NewTag.Tag.Id).toEqual(createShelfResponse.text);
-    expect(syncResponse.body[0].NewTag.Tag.Name).toEqual('new-name');
+    expect(syncResponse.body[0]).toHaveProperty('ChangedTag');
+    expect(syncResponse.body[0].ChangedTag).toHaveProperty('Tag');
+    expect(syncResponse.body[0].ChangedTag.Tag.Id).toEqual(createShelfResponse.text);
+    expect(syncResponse.body[0].ChangedTag.Tag.Name).toEqual('new-name');
+    expect(syncResponse.body[0].ChangedTag.Tag.Items).toHaveLength(0);
+    expect(syncResponse.body[0].ChangedTag.Tag.DeletedItems).toHaveLength(0);
   });
 
   test('Add book to shelf', async () => {
@@ -171,6 +175,43 @@ describe('Shelf syncing', () => {
     expect(syncResponse.body[1].NewTag.Tag.Items[0].RevisionId).toEqual(uploadBookResponse.text);
   });
 
+  test('Book order is preserved', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadFirstBookResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadFirstBookResponse.status).toBe(200);
+
+    const uploadSecondBookResponse = await uploadBook(userId, 'The_Great_Gatsby.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadSecondBookResponse.status).toBe(200);
+
+    const uploadThirdBookResponse = await uploadBook(userId, 'The_Wonderful_Wizard_of_Oz.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadThirdBookResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Create', 'Read', 'Update'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const createShelfResponse = await createShelf('new-shelf', undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createShelfResponse.status).toBe(200);
+
+    const order = [uploadThirdBookResponse.text, uploadFirstBookResponse.text, uploadSecondBookResponse.text];
+    const addBookToShelfResponse = await addBooksToShelf(createShelfResponse.text, order, authResponse.body.AccessToken);
+    expect(addBookToShelfResponse.status).toBe(201);
+
+    const syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+
+    const newTag = syncResponse.body.find((item: any) => item.NewTag !== undefined);
+    expect(newTag.NewTag.Tag.Items.map((item: any) => item.RevisionId)).toEqual(order);
+  });
+
   test('Remove book from shelf', async () => {
     const { response: registerResponse } = await registerUser();
     expect(registerResponse.status).toBe(200);
@@ -210,11 +251,13 @@ describe('Shelf syncing', () => {
     syncResponse = await sync(undefined, authResponse.body.AccessToken);
     expect(syncResponse.status).toBe(200);
     expect(syncResponse.body).toHaveLength(2);
-    expect(syncResponse.body[1]).toHaveProperty('NewTag');
-    expect(syncResponse.body[1].NewTag).toHaveProperty('Tag');
-    expect(syncResponse.body[1].NewTag.Tag.Id).toEqual(createShelfResponse.text);
-    expect(syncResponse.body[1].NewTag.Tag.Name).toEqual('new-shelf');
-    expect(syncResponse.body[1].NewTag.Tag.Items).toHaveLength(0);
+    expect(syncResponse.body[1]).toHaveProperty('ChangedTag');
+    expect(syncResponse.body[1].ChangedTag).toHaveProperty('Tag');
+    expect(syncResponse.body[1].ChangedTag.Tag.Id).toEqual(createShelfResponse.text);
+    expect(syncResponse.body[1].ChangedTag.Tag.Name).toBeUndefined();
+    expect(syncResponse.body[1].ChangedTag.Tag.Items).toHaveLength(0);
+    expect(syncResponse.body[1].ChangedTag.Tag.DeletedItems).toHaveLength(1);
+    expect(syncResponse.body[1].ChangedTag.Tag.DeletedItems[0].RevisionId).toEqual(uploadBookResponse.text);
   });
 
   test('Delete shelf', async () => {
@@ -255,6 +298,506 @@ describe('Shelf syncing', () => {
   });
 });
 
+describe('Incremental shelf syncing', () => {
+  test('Only changed items are sent', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const firstUploadResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(firstUploadResponse.status).toBe(200);
+
+    const secondUploadResponse = await uploadBook(userId, 'The_Great_Gatsby.epub', { jwt: registerResponse.body.jwt_token });
+    expect(secondUploadResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Create', 'Read', 'Update'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const createShelfResponse = await createShelf('new-shelf', undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createShelfResponse.status).toBe(200);
+
+    let addBookToShelfResponse = await addBookToShelf(createShelfResponse.text, firstUploadResponse.text, { jwt: registerResponse.body.jwt_token });
+    expect(addBookToShelfResponse.status).toBe(204);
+
+    let syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(3);
+    expect(syncResponse.body[2]).toHaveProperty('NewTag');
+    expect(syncResponse.body[2].NewTag.Tag.Items).toHaveLength(1);
+
+    addBookToShelfResponse = await addBookToShelf(createShelfResponse.text, secondUploadResponse.text, { jwt: registerResponse.body.jwt_token });
+    expect(addBookToShelfResponse.status).toBe(204);
+
+    syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(1);
+    expect(syncResponse.body[0]).toHaveProperty('ChangedTag');
+    expect(syncResponse.body[0].ChangedTag.Tag.Id).toEqual(createShelfResponse.text);
+    expect(syncResponse.body[0].ChangedTag.Tag.Items).toEqual([{ RevisionId: secondUploadResponse.text, Type: 'ProductRevisionTagItem' }]);
+    expect(syncResponse.body[0].ChangedTag.Tag.DeletedItems).toEqual([]);
+  });
+
+  test('Changes made by the device are not sent back', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadBookResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadBookResponse.status).toBe(200);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Create', 'Read', 'Update'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    let syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(1);
+
+    const createShelfResponse = await createKoboShelf('new-shelf', authResponse.body.AccessToken);
+    expect(createShelfResponse.status).toBe(201);
+
+    const addBookToShelfResponse = await addBooksToShelf(createShelfResponse.text, [uploadBookResponse.text], authResponse.body.AccessToken);
+    expect(addBookToShelfResponse.status).toBe(201);
+
+    syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(0);
+  });
+});
+
+describe('Smart shelf syncing', () => {
+  test('Simple', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const uploadBookResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(uploadBookResponse.status).toBe(200);
+
+    // Wait for metadata to be extracted
+    await wait(1);
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Create', 'Read', 'Update'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const setRulesResponse = await setSmartShelfRules(deviceId, ['currently_reading', 'unread', 'author'], createApiKeyResponse.body.key);
+    expect(setRulesResponse.status).toBe(204);
+
+    let syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(4);
+    expect(syncResponse.body[0]).toHaveProperty('NewEntitlement');
+
+    let tags = syncResponse.body.filter((item: any) => item.NewTag).map((item: any) => item.NewTag.Tag);
+    expect(tags.map((tag: any) => tag.Name)).toEqual(['Currently reading', 'Unread', 'Lewis Carroll']);
+    expect(tags[0].Items).toEqual([]);
+    expect(tags[1].Items).toEqual([{ RevisionId: uploadBookResponse.text, Type: 'ProductRevisionTagItem' }]);
+    expect(tags[2].Items).toEqual([{ RevisionId: uploadBookResponse.text, Type: 'ProductRevisionTagItem' }]);
+
+    // Unchanged smart shelves are not sent again
+    syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(0);
+
+    const updateStateResponse = await updateState(uploadBookResponse.text, 'kobo.4.2', 'OEBPS/229714655232534212_11-h-4.htm.xhtml', 'Reading', authResponse.body.AccessToken);
+    expect(updateStateResponse.status).toBe(200);
+
+    syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+
+    tags = syncResponse.body.filter((item: any) => item.ChangedTag).map((item: any) => item.ChangedTag.Tag);
+    expect(tags).toHaveLength(2);
+    expect(tags[0].Items).toEqual([{ RevisionId: uploadBookResponse.text, Type: 'ProductRevisionTagItem' }]);
+    expect(tags[0].DeletedItems).toEqual([]);
+    expect(tags[1].Items).toEqual([]);
+    expect(tags[1].DeletedItems).toEqual([{ RevisionId: uploadBookResponse.text, Type: 'ProductRevisionTagItem' }]);
+  });
+
+  test('Series', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const aliceResponse = await uploadBook(userId, 'Alices_Adventures_in_Wonderland.epub', { jwt: registerResponse.body.jwt_token });
+    expect(aliceResponse.status).toBe(200);
+
+    const ozResponse = await uploadBook(userId, 'The_Wonderful_Wizard_of_Oz.epub', { jwt: registerResponse.body.jwt_token });
+    expect(ozResponse.status).toBe(200);
+
+    const gatsbyResponse = await uploadBook(userId, 'The_Great_Gatsby.epub', { jwt: registerResponse.body.jwt_token });
+    expect(gatsbyResponse.status).toBe(200);
+
+    // Wait for metadata to be extracted
+    await wait(1);
+
+    const series = [
+      { bookId: aliceResponse.text, author: 'Lewis Carroll', number: 2 },
+      { bookId: ozResponse.text, author: 'Lewis Carroll', number: 1.5 },
+      { bookId: gatsbyResponse.text, author: 'F. Scott Fitzgerald', number: 1 }
+    ];
+
+    for (const { bookId, author, number } of series) {
+      const metadata = {
+        contributors: [{ name: author, role: 'Author' }],
+        series: { title: 'Classics', number: number }
+      };
+
+      const updateMetadataResponse = await updateMetadata(bookId, metadata, { jwt: registerResponse.body.jwt_token });
+      expect(updateMetadataResponse.status).toBe(204);
+    }
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const setRulesResponse = await setSmartShelfRules(deviceId, ['series'], createApiKeyResponse.body.key);
+    expect(setRulesResponse.status).toBe(204);
+
+    const syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+
+    // Series sharing a name only share a shelf when they share an author
+    const tags = syncResponse.body.filter((item: any) => item.NewTag).map((item: any) => item.NewTag.Tag);
+    expect(tags).toHaveLength(2);
+    expect(tags.map((tag: any) => tag.Name)).toEqual(['Classics', 'Classics']);
+
+    const items = tags.map((tag: any) => tag.Items.map((item: any) => item.RevisionId));
+    expect(items).toContainEqual([ozResponse.text, aliceResponse.text]);
+    expect(items).toContainEqual([gatsbyResponse.text]);
+  });
+
+  test('Removed rule', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Create', 'Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    let setRulesResponse = await setSmartShelfRules(deviceId, ['unread'], createApiKeyResponse.body.key);
+    expect(setRulesResponse.status).toBe(204);
+
+    let syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(1);
+    expect(syncResponse.body[0]).toHaveProperty('NewTag');
+    const shelfId = syncResponse.body[0].NewTag.Tag.Id;
+
+    setRulesResponse = await setSmartShelfRules(deviceId, [], createApiKeyResponse.body.key);
+    expect(setRulesResponse.status).toBe(204);
+
+    syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(1);
+    expect(syncResponse.body[0]).toHaveProperty('DeletedTag');
+    expect(syncResponse.body[0].DeletedTag.Tag.Id).toEqual(shelfId);
+  });
+
+  test('Read-only', async () => {
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Create', 'Read', 'Update', 'Delete'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const setRulesResponse = await setSmartShelfRules(deviceId, ['unread'], createApiKeyResponse.body.key);
+    expect(setRulesResponse.status).toBe(204);
+
+    let syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(1);
+    const shelfId = syncResponse.body[0].NewTag.Tag.Id;
+
+    const renameShelfResponse = await renameShelf(shelfId, 'new-name', authResponse.body.AccessToken);
+    expect(renameShelfResponse.status).toBe(403);
+    expect(renameShelfResponse.body.message).toBe(READ_ONLY_SHELF);
+
+    const deleteShelfResponse = await deleteKoboShelf(shelfId, authResponse.body.AccessToken);
+    expect(deleteShelfResponse.status).toBe(403);
+    expect(deleteShelfResponse.body.message).toBe(READ_ONLY_SHELF);
+
+    // The device gets the shelf back on its next sync
+    syncResponse = await sync(undefined, authResponse.body.AccessToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(1);
+    expect(syncResponse.body[0]).toHaveProperty('NewTag');
+    expect(syncResponse.body[0].NewTag.Tag.Id).toEqual(shelfId);
+    expect(syncResponse.body[0].NewTag.Tag.Name).toEqual('Unread');
+  });
+});
+
+// The owner is an admin, so its shelf can hold a book of the recipient next to a book the recipient can't access
+async function setupSharedShelf(accept = true) {
+  const { response: ownerResponse } = await registerUser(undefined, undefined, ADMIN_KEY);
+  expect(ownerResponse.status).toBe(200);
+  const ownerId = ownerResponse.body.user_id;
+  const ownerJwt = ownerResponse.body.jwt_token;
+
+  const { response: recipientResponse } = await registerUser();
+  expect(recipientResponse.status).toBe(200);
+  const recipientId = recipientResponse.body.user_id;
+
+  const recipientBookResponse = await uploadBook(recipientId, 'Alices_Adventures_in_Wonderland.epub', { jwt: recipientResponse.body.jwt_token });
+  expect(recipientBookResponse.status).toBe(200);
+
+  const ownerBookResponse = await uploadBook(ownerId, 'The_Great_Gatsby.epub', { jwt: ownerJwt });
+  expect(ownerBookResponse.status).toBe(200);
+
+  const createShelfResponse = await createShelf('Family', ownerId, { jwt: ownerJwt });
+  expect(createShelfResponse.status).toBe(200);
+  const shelfId = createShelfResponse.text;
+
+  for (const bookId of [recipientBookResponse.text, ownerBookResponse.text]) {
+    const addBookToShelfResponse = await addBookToShelf(shelfId, bookId, { jwt: ownerJwt });
+    expect(addBookToShelfResponse.status).toBe(204);
+  }
+
+  const ownerKeyResponse = await createApiKey(ownerId, 'Test Key', ['Create', 'Read', 'Update', 'Delete'], undefined, { jwt: ownerJwt });
+  expect(ownerKeyResponse.status).toBe(200);
+
+  const recipientKeyResponse = await createApiKey(recipientId, 'Test Key', ['Create', 'Read', 'Update', 'Delete'], undefined, { jwt: recipientResponse.body.jwt_token });
+  expect(recipientKeyResponse.status).toBe(200);
+
+  const { response: ownerAuthResponse, deviceId: ownerDeviceId } = await authDevice();
+  expect(ownerAuthResponse.status).toBe(200);
+
+  let linkResponse = await linkDevice(ownerDeviceId, ownerKeyResponse.body.key);
+  expect(linkResponse.status).toBe(200);
+
+  const { response: recipientAuthResponse, deviceId: recipientDeviceId } = await authDevice();
+  expect(recipientAuthResponse.status).toBe(200);
+
+  linkResponse = await linkDevice(recipientDeviceId, recipientKeyResponse.body.key);
+  expect(linkResponse.status).toBe(200);
+
+  const shared = [{ shelf_id: shelfId, shared_with: [recipientId] }];
+  const setSharedShelvesResponse = await setSharedShelves(ownerDeviceId, shared, ownerKeyResponse.body.key);
+  expect(setSharedShelvesResponse.status).toBe(204);
+
+  if (accept) {
+    const setAcceptedShelvesResponse = await setAcceptedShelves(recipientDeviceId, [{ shelf_id: shelfId, user_id: recipientId }], recipientKeyResponse.body.key);
+    expect(setAcceptedShelvesResponse.status).toBe(204);
+  }
+
+  return {
+    ownerId,
+    ownerJwt,
+    ownerKey: ownerKeyResponse.body.key,
+    ownerDeviceId,
+    recipientId,
+    recipientJwt: recipientResponse.body.jwt_token,
+    recipientKey: recipientKeyResponse.body.key,
+    recipientDeviceId,
+    recipientToken: recipientAuthResponse.body.AccessToken,
+    recipientBookId: recipientBookResponse.text,
+    shelfId
+  };
+}
+
+describe('Shared shelf syncing', () => {
+  test('Mirrored', async () => {
+    const { ownerKey, ownerDeviceId, recipientId, recipientKey, recipientDeviceId, recipientToken, recipientBookId, shelfId } = await setupSharedShelf();
+
+    const getSharedShelvesResponse = await getSharedShelves(ownerDeviceId, ownerKey);
+    expect(getSharedShelvesResponse.status).toBe(200);
+    expect(getSharedShelvesResponse.body).toEqual({ shelves: [{ shelf_id: shelfId, shared_with: [recipientId] }] });
+
+    const getAcceptedShelvesResponse = await getAcceptedShelves(recipientDeviceId, recipientKey);
+    expect(getAcceptedShelvesResponse.status).toBe(200);
+    expect(getAcceptedShelvesResponse.body).toEqual({ shelves: [{ shelf_id: shelfId, user_id: recipientId }] });
+
+    let syncResponse = await sync(undefined, recipientToken);
+    expect(syncResponse.status).toBe(200);
+
+    // Only the books the recipient can access are mirrored
+    const tags = syncResponse.body.filter((item: any) => item.NewTag).map((item: any) => item.NewTag.Tag);
+    expect(tags).toHaveLength(1);
+    expect(tags[0].Id).toEqual(shelfId);
+    expect(tags[0].Name).toEqual('Family');
+    expect(tags[0].Items).toEqual([{ RevisionId: recipientBookId, Type: 'ProductRevisionTagItem' }]);
+
+    syncResponse = await sync(undefined, recipientToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(0);
+  });
+
+  test('Owner changes', async () => {
+    const { ownerJwt, recipientId, recipientJwt, recipientToken, recipientBookId, shelfId } = await setupSharedShelf();
+
+    let syncResponse = await sync(undefined, recipientToken);
+    expect(syncResponse.status).toBe(200);
+
+    const secondBookResponse = await uploadBook(recipientId, 'The_Wonderful_Wizard_of_Oz.epub', { jwt: recipientJwt });
+    expect(secondBookResponse.status).toBe(200);
+
+    const addBookToShelfResponse = await addBookToShelf(shelfId, secondBookResponse.text, { jwt: ownerJwt });
+    expect(addBookToShelfResponse.status).toBe(204);
+
+    const deleteBookFromShelfResponse = await deleteBookFromShelf(shelfId, recipientBookId, { jwt: ownerJwt });
+    expect(deleteBookFromShelfResponse.status).toBe(204);
+
+    const updateShelfResponse = await updateShelf(shelfId, 'Family favourites', { jwt: ownerJwt });
+    expect(updateShelfResponse.status).toBe(204);
+
+    syncResponse = await sync(undefined, recipientToken);
+    expect(syncResponse.status).toBe(200);
+
+    const tags = syncResponse.body.filter((item: any) => item.ChangedTag).map((item: any) => item.ChangedTag.Tag);
+    expect(tags).toHaveLength(1);
+    expect(tags[0].Id).toEqual(shelfId);
+    expect(tags[0].Name).toEqual('Family favourites');
+    expect(tags[0].Items).toEqual([{ RevisionId: secondBookResponse.text, Type: 'ProductRevisionTagItem' }]);
+    expect(tags[0].DeletedItems).toEqual([{ RevisionId: recipientBookId, Type: 'ProductRevisionTagItem' }]);
+  });
+
+  test('Read-only', async () => {
+    const { recipientToken, recipientBookId, shelfId } = await setupSharedShelf();
+
+    let syncResponse = await sync(undefined, recipientToken);
+    expect(syncResponse.status).toBe(200);
+
+    const renameShelfResponse = await renameShelf(shelfId, 'new-name', recipientToken);
+    expect(renameShelfResponse.status).toBe(403);
+    expect(renameShelfResponse.body.message).toBe(SHARED_SHELF);
+
+    const addBooksToShelfResponse = await addBooksToShelf(shelfId, [recipientBookId], recipientToken);
+    expect(addBooksToShelfResponse.status).toBe(403);
+    expect(addBooksToShelfResponse.body.message).toBe(SHARED_SHELF);
+
+    const deleteShelfResponse = await deleteKoboShelf(shelfId, recipientToken);
+    expect(deleteShelfResponse.status).toBe(403);
+    expect(deleteShelfResponse.body.message).toBe(SHARED_SHELF);
+
+    // The device gets the shelf back on its next sync
+    syncResponse = await sync(undefined, recipientToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(1);
+    expect(syncResponse.body[0]).toHaveProperty('NewTag');
+    expect(syncResponse.body[0].NewTag.Tag.Id).toEqual(shelfId);
+    expect(syncResponse.body[0].NewTag.Tag.Name).toEqual('Family');
+  });
+
+  test('Revoked', async () => {
+    const { ownerKey, ownerDeviceId, recipientToken, shelfId } = await setupSharedShelf();
+
+    let syncResponse = await sync(undefined, recipientToken);
+    expect(syncResponse.status).toBe(200);
+
+    const setSharedShelvesResponse = await setSharedShelves(ownerDeviceId, [], ownerKey);
+    expect(setSharedShelvesResponse.status).toBe(204);
+
+    syncResponse = await sync(undefined, recipientToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(1);
+    expect(syncResponse.body[0]).toHaveProperty('DeletedTag');
+    expect(syncResponse.body[0].DeletedTag.Tag.Id).toEqual(shelfId);
+  });
+
+  test('Owner unlinked', async () => {
+    const { ownerKey, ownerDeviceId, recipientToken, shelfId } = await setupSharedShelf();
+
+    let syncResponse = await sync(undefined, recipientToken);
+    expect(syncResponse.status).toBe(200);
+
+    const unlinkResponse = await unlinkDevice(ownerDeviceId, ownerKey);
+    expect(unlinkResponse.status).toBe(200);
+
+    syncResponse = await sync(undefined, recipientToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body).toHaveLength(1);
+    expect(syncResponse.body[0]).toHaveProperty('DeletedTag');
+    expect(syncResponse.body[0].DeletedTag.Tag.Id).toEqual(shelfId);
+  });
+
+  test('Shelf of another user', async () => {
+    const { response: ownerResponse } = await registerUser();
+    expect(ownerResponse.status).toBe(200);
+    const ownerId = ownerResponse.body.user_id;
+
+    const { response: registerResponse } = await registerUser();
+    expect(registerResponse.status).toBe(200);
+    const userId = registerResponse.body.user_id;
+
+    const createApiKeyResponse = await createApiKey(userId, 'Test Key', ['Read'], undefined, { jwt: registerResponse.body.jwt_token });
+    expect(createApiKeyResponse.status).toBe(200);
+
+    const { response: authResponse, deviceId } = await authDevice();
+    expect(authResponse.status).toBe(200);
+
+    const linkResponse = await linkDevice(deviceId, createApiKeyResponse.body.key);
+    expect(linkResponse.status).toBe(200);
+
+    const createShelfResponse = await createShelf('Not mine', ownerId, { jwt: ownerResponse.body.jwt_token });
+    expect(createShelfResponse.status).toBe(200);
+
+    // Only the owner of a shelf can share it
+    const shared = [{ shelf_id: createShelfResponse.text, shared_with: [] }];
+    const setSharedShelvesResponse = await setSharedShelves(deviceId, shared, createApiKeyResponse.body.key);
+    expect(setSharedShelvesResponse.status).toBe(403);
+  });
+
+  test('Not accepted', async () => {
+    const { recipientToken } = await setupSharedShelf(false);
+
+    const syncResponse = await sync(undefined, recipientToken);
+    expect(syncResponse.status).toBe(200);
+    expect(syncResponse.body.filter((item: any) => item.NewTag)).toHaveLength(0);
+  });
+
+  test('Accepted as another user', async () => {
+    const { ownerId, recipientKey, recipientDeviceId, shelfId } = await setupSharedShelf(false);
+
+    const setAcceptedShelvesResponse = await setAcceptedShelves(recipientDeviceId, [{ shelf_id: shelfId, user_id: ownerId }], recipientKey);
+    expect(setAcceptedShelvesResponse.status).toBe(403);
+  });
+
+  test('Accepted without a share', async () => {
+    const { ownerKey, ownerDeviceId, recipientId, recipientKey, recipientDeviceId, shelfId } = await setupSharedShelf(false);
+
+    const setSharedShelvesResponse = await setSharedShelves(ownerDeviceId, [], ownerKey);
+    expect(setSharedShelvesResponse.status).toBe(204);
+
+    const setAcceptedShelvesResponse = await setAcceptedShelves(recipientDeviceId, [{ shelf_id: shelfId, user_id: recipientId }], recipientKey);
+    expect(setAcceptedShelvesResponse.status).toBe(404);
+    expect(setAcceptedShelvesResponse.body.message).toBe(SHELF_SHARE_NOT_FOUND);
+  });
+});
+
 describe('Errors', () => {
   test('No auth', async () => {
     const syncResponse = await sync();
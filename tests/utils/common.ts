@@ -1,5 +1,7 @@
 export const MIDDLEWARE_URL = requiredEnv('MIDDLEWARE_URL');
 export const PROSA_URL = requiredEnv('PROSA_URL');
+export const COVER_EXPIRATION = Number(requiredEnv('COVER_EXPIRATION'));
+export const ADMIN_KEY = requiredEnv('ADMIN_KEY');
 export const BOOK_DIR = 'books/';
 
 export const INVALID_TOKEN = 'Invalid token';
@@ -1,3 +1,4 @@
+import { randomUUID } from 'crypto';
 import request from 'supertest';
 import { MIDDLEWARE_URL } from '../common';
 
@@ -6,27 +7,39 @@ type checkContentRequest = {
   etag: string;
 };
 
-export async function checkForChanges(books: checkContentRequest[]) {
+export async function checkForChanges(books: checkContentRequest[], jwt?: string) {
   let req = request(MIDDLEWARE_URL).post(`/api/v3/content/checkforchanges`);
 
+  if (jwt !== undefined) req = req.auth(jwt, { type: 'bearer' });
+
   return req.send(books);
 }
 
-export async function getAnnotations(bookId: string, jwt?: string) {
+export const INVALID_PAGE_TOKEN = 'The provided page offset token is invalid.';
+export const MISSING_API_KEY = 'The api key must be provided.';
+
+export async function getAnnotations(bookId: string, jwt?: string, pageSize?: number, pageOffsetToken?: string) {
   let req = request(MIDDLEWARE_URL).get(`/api/v3/content/${bookId}/annotations`);
 
   if (jwt !== undefined) req = req.auth(jwt, { type: 'bearer' });
+  if (pageSize !== undefined) req = req.query({ pageSize: pageSize });
+  if (pageOffsetToken !== undefined) req = req.query({ pageOffsetToken: pageOffsetToken });
 
   return req.send();
 }
 
 export type annotationRequest = {
+  id?: string;
   startChar: number;
   startTag: string;
   endChar: number;
   endTag: string;
   source: string;
   note?: string;
+  clientCreatedUtc?: string;
+  clientLastModifiedUtc?: string;
+  highlightColor?: string;
+  highlightStyle?: string;
 };
 
 export async function addAnnotation(bookId: string, annotation: annotationRequest, jwt?: string) {
@@ -42,9 +55,9 @@ export async function addAnnotation(bookId: string, annotation: annotationReques
   const body: any = {
     updatedAnnotations: [
       {
-        clientLastModifiedUtc: 'placeholder',
+        clientLastModifiedUtc: annotation.clientLastModifiedUtc ?? 'placeholder',
         highlightedText: 'placeholder',
-        id: 'placeholder',
+        id: annotation.id ?? randomUUID(),
         location: {
           span: {
             chapterFilename: annotation.source,
@@ -65,6 +78,10 @@ export async function addAnnotation(bookId: string, annotation: annotationReques
     body.updatedAnnotations[0].noteText = annotation.note;
   }
 
+  if (annotation.clientCreatedUtc !== undefined) body.updatedAnnotations[0].clientCreatedUtc = annotation.clientCreatedUtc;
+  if (annotation.highlightColor !== undefined) body.updatedAnnotations[0].highlightColor = annotation.highlightColor;
+  if (annotation.highlightStyle !== undefined) body.updatedAnnotations[0].highlightStyle = annotation.highlightStyle;
+
   return req.send(body);
 }
 
@@ -81,7 +98,7 @@ export async function updateAnnotation(bookId: string, annotationId: string, ann
   const body: any = {
     updatedAnnotations: [
       {
-        clientLastModifiedUtc: 'placeholder',
+        clientLastModifiedUtc: annotation.clientLastModifiedUtc ?? 'placeholder',
         highlightedText: 'placeholder',
         id: annotationId,
         location: {
@@ -104,6 +121,10 @@ export async function updateAnnotation(bookId: string, annotationId: string, ann
     body.updatedAnnotations[0].noteText = annotation.note;
   }
 
+  if (annotation.clientCreatedUtc !== undefined) body.updatedAnnotations[0].clientCreatedUtc = annotation.clientCreatedUtc;
+  if (annotation.highlightColor !== undefined) body.updatedAnnotations[0].highlightColor = annotation.highlightColor;
+  if (annotation.highlightStyle !== undefined) body.updatedAnnotations[0].highlightStyle = annotation.highlightStyle;
+
   return req.send(body);
 }
 
@@ -116,3 +137,21 @@ export async function deleteAnnotation(bookId: string, annotationId: string, jwt
 
   return req.send(body);
 }
+
+export async function patchAnnotations(bookId: string, body: any, jwt?: string) {
+  let req = request(MIDDLEWARE_URL).patch(`/api/v3/content/${bookId}/annotations`);
+
+  if (jwt !== undefined) req = req.auth(jwt, { type: 'bearer' });
+
+  return req.send(body);
+}
+
+export async function exportAnnotations(apiKey?: string, format?: string, bookId?: string) {
+  let req = request(MIDDLEWARE_URL).get('/annotations/export');
+
+  if (apiKey !== undefined) req = req.set('api-key', apiKey);
+  if (format !== undefined) req = req.query({ format: format });
+  if (bookId !== undefined) req = req.query({ book_id: bookId });
+
+  return req.send();
+}
@@ -0,0 +1,44 @@
+import { createHash } from 'crypto';
+import request from 'supertest';
+import { MIDDLEWARE_URL } from '../common';
+
+export const CATEGORY_NOT_FOUND = 'The requested category was not found.';
+
+export const ALICE_GENRES = ['Fantasy fiction', "Children's stories", 'Imaginary places -- Juvenile fiction', 'Alice (Fictitious character from Carroll) -- Juvenile fiction'];
+
+export async function getCategories(jwt?: string) {
+  let req = request(MIDDLEWARE_URL).get('/v1/categories');
+
+  if (jwt !== undefined) req = req.auth(jwt, { type: 'bearer' });
+
+  return req.send();
+}
+
+export async function getCategory(categoryId: string, jwt?: string) {
+  let req = request(MIDDLEWARE_URL).get(`/v1/categories/${categoryId}`);
+
+  if (jwt !== undefined) req = req.auth(jwt, { type: 'bearer' });
+
+  return req.send();
+}
+
+export async function getCategoryFeaturedLists(categoryId: string, jwt?: string) {
+  let req = request(MIDDLEWARE_URL).get(`/v1/categories/${categoryId}/featured`);
+
+  if (jwt !== undefined) req = req.auth(jwt, { type: 'bearer' });
+
+  return req.send();
+}
+
+export async function getCategoryProducts(categoryId: string, jwt?: string) {
+  let req = request(MIDDLEWARE_URL).get(`/v1/categories/${categoryId}/products`);
+
+  if (jwt !== undefined) req = req.auth(jwt, { type: 'bearer' });
+
+  return req.send();
+}
+
+export function syntheticCategoryId(genre: string) {
+  const hex = createHash('sha256').update(genre.trim().split(/\s+/).join(' ').toLowerCase()).digest('hex');
+  return `${hex.slice(0, 8)}-${hex.slice(8, 12)}-${hex.slice(12, 16)}-${hex.slice(16, 20)}-${hex.slice(20, 32)}`;
+}
@@ -12,3 +12,12 @@ export async function getCover(bookId: string, height?: number, width?: number,
 
   return req.send();
 }
+
+export async function getTemplatedCover(imageId: string, width: number, height: number, quality?: number, greyscale: boolean = false, etag?: string) {
+  const path = quality === undefined ? `${width}/${height}/${greyscale}` : `${width}/${height}/${quality}/${greyscale}`;
+  let req = request(MIDDLEWARE_URL).get(`/images/${imageId}/${path}/image.jpg`);
+
+  if (etag !== undefined) req = req.set('If-None-Match', etag);
+
+  return req.send();
+}
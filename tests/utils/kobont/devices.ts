@@ -7,6 +7,7 @@ export const DEVICE_ALREADY_LINKED = 'This device is already linked.';
 export const DEVICE_ALREADY_UNLINKED = 'This device is already unlinked.';
 export const INVALID_API_KEY = 'The provided api key is invalid.';
 export const MISSING_API_KEY = 'The api key must be provided.';
+export const SHELF_SHARE_NOT_FOUND = 'The shelf is not shared with this user.';
 
 function generateDeviceId(deviceId: string, userKey: string): string {
   const hash = createHash('sha256')
@@ -46,6 +47,14 @@ export async function unlinkDevice(device_id: string, api_key: string) {
   return req.send();
 }
 
+export async function getDeviceActivity(device_id: string, api_key?: string) {
+  let req = request(MIDDLEWARE_URL).get(`/devices/linked/${device_id}/activity`);
+
+  if (api_key !== undefined) req = req.set('api-key', api_key);
+
+  return req.send();
+}
+
 export async function authDevice(deviceId?: string, userKey?: string) {
   let req = request(MIDDLEWARE_URL).post('/v1/auth/device');
 
@@ -81,3 +90,51 @@ export async function authRefreshDevice(refreshToken: string) {
 
   return req.send(body);
 }
+
+export async function getSmartShelfRules(device_id: string, api_key?: string) {
+  let req = request(MIDDLEWARE_URL).get(`/devices/linked/${device_id}/smart-shelves`);
+
+  if (api_key !== undefined) req = req.set('api-key', api_key);
+
+  return req.send();
+}
+
+export async function setSmartShelfRules(device_id: string, rules: string[], api_key?: string) {
+  let req = request(MIDDLEWARE_URL).put(`/devices/linked/${device_id}/smart-shelves`);
+
+  if (api_key !== undefined) req = req.set('api-key', api_key);
+
+  return req.send({ rules: rules });
+}
+
+export async function getSharedShelves(device_id: string, api_key?: string) {
+  let req = request(MIDDLEWARE_URL).get(`/devices/linked/${device_id}/shared-shelves`);
+
+  if (api_key !== undefined) req = req.set('api-key', api_key);
+
+  return req.send();
+}
+
+export async function setSharedShelves(device_id: string, shelves: { shelf_id: string; shared_with: string[] }[], api_key?: string) {
+  let req = request(MIDDLEWARE_URL).put(`/devices/linked/${device_id}/shared-shelves`);
+
+  if (api_key !== undefined) req = req.set('api-key', api_key);
+
+  return req.send({ shelves: shelves });
+}
+
+export async function getAcceptedShelves(device_id: string, api_key?: string) {
+  let req = request(MIDDLEWARE_URL).get(`/devices/linked/${device_id}/accepted-shelves`);
+
+  if (api_key !== undefined) req = req.set('api-key', api_key);
+
+  return req.send();
+}
+
+export async function setAcceptedShelves(device_id: string, shelves: { shelf_id: string; user_id: string }[], api_key?: string) {
+  let req = request(MIDDLEWARE_URL).put(`/devices/linked/${device_id}/accepted-shelves`);
+
+  if (api_key !== undefined) req = req.set('api-key', api_key);
+
+  return req.send({ shelves: shelves });
+}
@@ -1,5 +1,6 @@
 import request from 'supertest';
 import { MIDDLEWARE_URL } from '../common';
+import { ALICE_GENRES, syntheticCategoryId } from './categories';
 
 export async function getMetadata(bookId: string, jwt?: string) {
   let req = request(MIDDLEWARE_URL).get(`/v1/library/${bookId}/metadata`);
@@ -14,7 +15,11 @@ export async function generateAliceMetadata(bookId: string) {
   const url = new URL(MIDDLEWARE_URL);
   const replaced = template.replace(/{bookId}/g, bookId).replace(/{host}/g, url.host);
 
-  return JSON.parse(replaced);
+  const metadata = JSON.parse(replaced);
+  metadata[0].Genre = syntheticCategoryId(ALICE_GENRES[0]);
+  metadata[0].Categories = ALICE_GENRES.map(syntheticCategoryId);
+
+  return metadata;
 }
 
 export async function generateDefaultMetadata(bookId: string) {
@@ -28,7 +33,7 @@ export async function generateDefaultMetadata(bookId: string) {
 export function normalizeMetadata(book: any) {
   return {
     ...book,
-    CoverImageId: book.CoverImageId.split('?')[0],
+    CoverImageId: book.CoverImageId.split('/')[0],
     DownloadUrls: book.DownloadUrls.map((dl: any) => ({
       ...dl,
       Url: dl.Url.split('?')[0]
@@ -46,14 +51,14 @@ const ALICE_METADATA_TEMPLATE = [
     },
     PublicationDate: '2008-06-27T00:00:00.0000000Z',
     Language: 'en',
-    Isbn: 'http://www.gutenberg.org/11',
+    Isbn: null,
     Subtitle: null,
     Genre: null,
     Slug: null,
     CoverImageId: '{bookId}',
     IsSocialEnabled: true,
     WorkId: '{bookId}',
-    ExternalIds: [],
+    ExternalIds: ['http://www.gutenberg.org/11'],
     IsPreOrder: false,
     ContributorRoles: [
       {
@@ -90,7 +95,7 @@ const ALICE_METADATA_TEMPLATE = [
     PhoneticPronunciations: null,
     RelatedGroupId: null,
     Locale: {
-      LanguageCode: '',
+      LanguageCode: 'en',
       ScriptCode: '',
       CountryCode: ''
     }
@@ -0,0 +1,28 @@
+import request from 'supertest';
+import { MIDDLEWARE_URL } from '../common';
+
+export const OVERRIDE_NOT_FOUND = 'No metadata override exists for this book.';
+
+export async function getOverride(bookId: string, apiKey?: string) {
+  let req = request(MIDDLEWARE_URL).get(`/overrides/${bookId}`);
+
+  if (apiKey !== undefined) req = req.set('api-key', apiKey);
+
+  return req.send();
+}
+
+export async function setOverride(bookId: string, override: any, apiKey?: string) {
+  let req = request(MIDDLEWARE_URL).put(`/overrides/${bookId}`);
+
+  if (apiKey !== undefined) req = req.set('api-key', apiKey);
+
+  return req.send(override);
+}
+
+export async function deleteOverride(bookId: string, apiKey?: string) {
+  let req = request(MIDDLEWARE_URL).delete(`/overrides/${bookId}`);
+
+  if (apiKey !== undefined) req = req.set('api-key', apiKey);
+
+  return req.send();
+}
@@ -1,6 +1,9 @@
 import request from 'supertest';
 import { MIDDLEWARE_URL } from '../common';
 
+export const READ_ONLY_SHELF = 'Smart shelves are generated by the server and cannot be modified.';
+export const SHARED_SHELF = 'Shared shelves can only be modified by their owner.';
+
 export async function createShelf(shelfName: string, jwt?: string) {
   let req = request(MIDDLEWARE_URL).post(`/v1/library/tags`);
 
@@ -63,3 +66,11 @@ export async function deleteBooksFromShelf(shelfId: string, bookIds: string[], j
 
   return req.send(body);
 }
+
+export async function addItemsToShelf(shelfId: string, items: object[], jwt?: string) {
+  let req = request(MIDDLEWARE_URL).post(`/v1/library/tags/${shelfId}/items`);
+
+  if (jwt !== undefined) req = req.auth(jwt, { type: 'bearer' });
+
+  return req.send({ Items: items });
+}
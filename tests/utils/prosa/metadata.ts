@@ -9,3 +9,12 @@ export async function deleteMetadata(book_id: string, auth?: { jwt?: string; api
 
   return req.send();
 }
+
+export async function updateMetadata(book_id: string, metadata: any, auth?: { jwt?: string; apiKey?: string }) {
+  let req = request(PROSA_URL).put(`/books/${book_id}/metadata`);
+
+  if (auth?.jwt) req = req.auth(auth.jwt, { type: 'bearer' });
+  if (auth?.apiKey) req = req.set('api-key', auth.apiKey);
+
+  return req.send(metadata);
+}
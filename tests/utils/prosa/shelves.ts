@@ -41,6 +41,15 @@ export async function deleteShelf(shelfId: string, auth?: { jwt?: string; apiKey
   return req.send();
 }
 
+export async function addBookToShelf(shelfId: string, bookId: string, auth?: { jwt?: string; apiKey?: string }) {
+  let req = request(PROSA_URL).post(`/shelves/${shelfId}/books`);
+
+  if (auth?.jwt) req = req.auth(auth.jwt, { type: 'bearer' });
+  if (auth?.apiKey) req = req.set('api-key', auth.apiKey);
+
+  return req.send({ book_id: bookId });
+}
+
 export async function listBooksFromShelf(shelfId: string, auth?: { jwt?: string; apiKey?: string }) {
   let req = request(PROSA_URL).get(`/shelves/${shelfId}/books`);
 